@@ -1,15 +1,23 @@
 use crate::{
-    bxcad::{BXCADType, PosInTexture, BXCAD},
+    bxcad::{
+        dump::{Dump, LayoutReader},
+        serde_impl::{defaults, float},
+        BXCADType, PosInTexture, BXCAD,
+    },
     bytestream_addon::ByteStream,
     error::Result,
     Color, VarLenString,
 };
-use bytestream::{ByteOrder, StreamReader, StreamWriter};
+use bytestream::{ByteOrder, StreamWriter};
 use serde::{Deserialize, Serialize};
-use std::io::{Read, Seek, Write};
+use std::{
+    hash::{Hash, Hasher},
+    io::{Read, Seek, Write},
+};
 
 /// A representation of the contents of a BCCAD file
 #[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct BCCAD {
     /// Date of latest format revision, in YYYYMMDD format (decimal).
     /// Known timestamp is 20131007 (Oct 7 2013)
@@ -27,7 +35,16 @@ pub struct BCCAD {
 /// A frame of a BCCAD animation, composed of several [`SpritePart`]s or cells
 /// aligned together to create a full picture
 #[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(
+    feature = "schema",
+    derive(schemars::JsonSchema),
+    schemars(rename = "BCCADSprite")
+)]
 pub struct Sprite {
+    /// Name of the sprite, so that [`AnimationStep`]s can refer to it by name in indexized
+    /// JSON files. It's not stored in the BCCAD file itself
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
     /// [`SpritePart`]s that form this Sprite
     pub parts: Vec<SpritePart>,
 }
@@ -35,210 +52,257 @@ pub struct Sprite {
 /// A small image taken directly from the texture sheet, which grouped with others
 /// creates a full frame of the animation, that is, a [`Sprite`]
 #[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(
+    feature = "schema",
+    derive(schemars::JsonSchema),
+    schemars(rename = "BCCADSpritePart")
+)]
 pub struct SpritePart {
     /// Struct that defines the bounds of the SpritePart in the texture itself
     pub texture_pos: PosInTexture,
     /// X position where the part should be placed relative to the sprite
+    #[serde(default)]
     pub pos_x: i16,
     /// Y position where the part should be placed relative to the sprite
+    #[serde(default)]
     pub pos_y: i16,
     /// Scaling factor for the X axis
+    #[cfg_attr(feature = "schema", schemars(schema_with = "float::schema"))]
+    #[serde(default = "defaults::scale", with = "float")]
     pub scale_x: f32,
     /// Scaling factor for the Y axis
+    #[cfg_attr(feature = "schema", schemars(schema_with = "float::schema"))]
+    #[serde(default = "defaults::scale", with = "float")]
     pub scale_y: f32,
     /// Part rotation in degrees
+    #[cfg_attr(feature = "schema", schemars(schema_with = "float::schema"))]
+    #[serde(default, with = "float")]
     pub rotation: f32,
     /// Whether to flip the part on the X axis
+    #[serde(default)]
     pub flip_x: bool,
     /// Whether to flip the part on the Y axis
+    #[serde(default)]
     pub flip_y: bool,
     /// A color to apply the multiply blending mode with for the
     /// part in order to darken or tint it
+    #[serde(default = "defaults::multiply_color")]
     pub multiply_color: Color,
     /// A color to apply the screen blending mode with for the
     /// part in order to lighten it
+    #[serde(default)]
     pub screen_color: Color,
     /// Opacity for the part
+    #[serde(default = "defaults::opacity::<u8>")]
     pub opacity: u8,
+    #[serde(default)]
     pub unk1: [u8; 12],
     /// Seems to identify certain parts for the code to apply effects or other
     /// textures too, or use them as interactive pieces (see: Feed Goat game)
+    #[serde(default)]
     pub designation_id: u8,
+    #[serde(default)]
     pub unk2: u8,
     /// Stereoscopic depth for the part
+    #[serde(default)]
     pub depth: StereoDepth,
 }
 
 /// Stereoscopic depth in the four corners of a rectangle (on which a [`SpritePart`]
 /// is rendered)
-#[derive(Serialize, Deserialize, Clone)]
+///
+/// Defaults to no depth in any corner
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct StereoDepth {
     /// Top-left corner
+    #[cfg_attr(feature = "schema", schemars(schema_with = "float::schema"))]
+    #[serde(with = "float")]
     pub top_left: f32,
     /// Bottom-left corner
+    #[cfg_attr(feature = "schema", schemars(schema_with = "float::schema"))]
+    #[serde(with = "float")]
     pub bottom_left: f32,
     /// Top-right corner
+    #[cfg_attr(feature = "schema", schemars(schema_with = "float::schema"))]
+    #[serde(with = "float")]
     pub top_right: f32,
     /// Bottom-right corner
+    #[cfg_attr(feature = "schema", schemars(schema_with = "float::schema"))]
+    #[serde(with = "float")]
     pub bottom_right: f32,
 }
 
+// the name is only there for JSON files, so sprites with the same parts are the same sprite
+impl PartialEq for Sprite {
+    fn eq(&self, other: &Self) -> bool {
+        self.parts == other.parts
+    }
+}
+
+impl Eq for Sprite {}
+
+impl Hash for Sprite {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.parts.hash(state);
+    }
+}
+
+impl PartialEq for SpritePart {
+    fn eq(&self, other: &Self) -> bool {
+        self.texture_pos == other.texture_pos
+            && self.pos_x == other.pos_x
+            && self.pos_y == other.pos_y
+            && float::bits(self.scale_x) == float::bits(other.scale_x)
+            && float::bits(self.scale_y) == float::bits(other.scale_y)
+            && float::bits(self.rotation) == float::bits(other.rotation)
+            && self.flip_x == other.flip_x
+            && self.flip_y == other.flip_y
+            && self.multiply_color == other.multiply_color
+            && self.screen_color == other.screen_color
+            && self.opacity == other.opacity
+            && self.unk1 == other.unk1
+            && self.designation_id == other.designation_id
+            && self.unk2 == other.unk2
+            && self.depth == other.depth
+    }
+}
+
+impl Eq for SpritePart {}
+
+impl Hash for SpritePart {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.texture_pos.hash(state);
+        self.pos_x.hash(state);
+        self.pos_y.hash(state);
+        float::bits(self.scale_x).hash(state);
+        float::bits(self.scale_y).hash(state);
+        float::bits(self.rotation).hash(state);
+        self.flip_x.hash(state);
+        self.flip_y.hash(state);
+        self.multiply_color.hash(state);
+        self.screen_color.hash(state);
+        self.opacity.hash(state);
+        self.unk1.hash(state);
+        self.designation_id.hash(state);
+        self.unk2.hash(state);
+        self.depth.hash(state);
+    }
+}
+
+impl PartialEq for StereoDepth {
+    fn eq(&self, other: &Self) -> bool {
+        float::bits(self.top_left) == float::bits(other.top_left)
+            && float::bits(self.bottom_left) == float::bits(other.bottom_left)
+            && float::bits(self.top_right) == float::bits(other.top_right)
+            && float::bits(self.bottom_right) == float::bits(other.bottom_right)
+    }
+}
+
+impl Eq for StereoDepth {}
+
+impl Hash for StereoDepth {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        float::bits(self.top_left).hash(state);
+        float::bits(self.bottom_left).hash(state);
+        float::bits(self.top_right).hash(state);
+        float::bits(self.bottom_right).hash(state);
+    }
+}
+
 /// A cell animation for BCCAD, composed of different frames/[`Sprite`]s
+///
+/// `S` is the type used to refer to sprites, see [`AnimationStep`]
 #[derive(Serialize, Deserialize, Clone)]
-pub struct Animation {
+#[cfg_attr(
+    feature = "schema",
+    derive(schemars::JsonSchema),
+    schemars(rename = "BCCADAnimation_for_{S}")
+)]
+pub struct Animation<S = u16> {
     /// The name of the animation. This is what the game refers to it by
     pub name: String,
     /// Amount of interpolation used ???
     pub interpolation: i32,
     /// List of [`AnimationStep`]s that constitute this Animation
-    pub steps: Vec<AnimationStep>,
+    pub steps: Vec<AnimationStep<S>>,
 }
 
 /// These constitute an [`Animation`], and are a reference to
 /// a [`Sprite`] plus more information about it relative to the
 /// whole animation
+///
+/// `S` is the type used to refer to the sprite: its index number in the BCCAD
+/// by default, or a [`SpriteRef`](crate::bxcad::qol::SpriteRef) in indexized BCCADs
 #[derive(Serialize, Deserialize, Clone)]
-pub struct AnimationStep {
+#[cfg_attr(
+    feature = "schema",
+    derive(schemars::JsonSchema),
+    schemars(rename = "BCCADAnimationStep_for_{S}")
+)]
+pub struct AnimationStep<S = u16> {
     /// A reference to the index number of the [`Sprite`] this AnimationStep uses
-    pub sprite: u16,
+    pub sprite: S,
     /// Duration of the frame (in unknown units, seems to have changeable speed)
     pub duration: u16,
     /// X position the sprite is rendered to, relative to the animation
+    #[serde(default)]
     pub pos_x: i16,
     /// Y position the sprite is rendered to, relative to the animation
+    #[serde(default)]
     pub pos_y: i16,
     /// Stereoscopic depth applied to the whole [`Sprite`]
+    #[cfg_attr(feature = "schema", schemars(schema_with = "float::schema"))]
+    #[serde(default, with = "float")]
     pub depth: f32,
     /// Scaling factor for the X axis
+    #[cfg_attr(feature = "schema", schemars(schema_with = "float::schema"))]
+    #[serde(default = "defaults::scale", with = "float")]
     pub scale_x: f32,
     /// Scaling factor for the Y axis
+    #[cfg_attr(feature = "schema", schemars(schema_with = "float::schema"))]
+    #[serde(default = "defaults::scale", with = "float")]
     pub scale_y: f32,
     /// Rotation in degrees
+    #[cfg_attr(feature = "schema", schemars(schema_with = "float::schema"))]
+    #[serde(default, with = "float")]
     pub rotation: f32,
     /// A color to apply the multiply blending mode with for the
     /// [`Sprite`] in order to darken or tint it
+    #[serde(default = "defaults::multiply_color")]
     pub multiply_color: Color,
+    #[serde(default)]
     pub unk: [u8; 3],
     /// Opacity for the sprite
+    #[serde(default = "defaults::opacity::<u16>")]
     pub opacity: u16,
 }
 
+impl<S> AnimationStep<S> {
+    /// Returns this same step, but referring to its sprite with the given value
+    pub fn with_sprite<T>(self, sprite: T) -> AnimationStep<T> {
+        AnimationStep {
+            sprite,
+            duration: self.duration,
+            pos_x: self.pos_x,
+            pos_y: self.pos_y,
+            depth: self.depth,
+            scale_x: self.scale_x,
+            scale_y: self.scale_y,
+            rotation: self.rotation,
+            multiply_color: self.multiply_color,
+            unk: self.unk,
+            opacity: self.opacity,
+        }
+    }
+}
+
 impl BXCAD for BCCAD {
     const BYTE_ORDER: ByteOrder = ByteOrder::LittleEndian;
     const TIMESTAMP: u32 = 20131007;
     const BXCAD_TYPE: BXCADType = BXCADType::BCCAD;
     fn from_binary<F: Read>(f: &mut F) -> Result<Self> {
-        let timestamp = u32::read_from(f, Self::BYTE_ORDER)?;
-        let texture_width = u16::read_from(f, Self::BYTE_ORDER)?;
-        let texture_height = u16::read_from(f, Self::BYTE_ORDER)?;
-
-        let sprite_count = u32::read_from(f, Self::BYTE_ORDER)?;
-        let mut sprites = vec![];
-        for _ in 0..sprite_count {
-            let parts_count = u32::read_from(f, Self::BYTE_ORDER)?;
-            let mut parts = vec![];
-            for _ in 0..parts_count {
-                let texture_pos = PosInTexture {
-                    x: u16::read_from(f, Self::BYTE_ORDER)?,
-                    y: u16::read_from(f, Self::BYTE_ORDER)?,
-                    width: u16::read_from(f, Self::BYTE_ORDER)?,
-                    height: u16::read_from(f, Self::BYTE_ORDER)?,
-                };
-                let pos_x = i16::read_from(f, Self::BYTE_ORDER)?;
-                let pos_y = i16::read_from(f, Self::BYTE_ORDER)?;
-                let scale_x = f32::read_from(f, Self::BYTE_ORDER)?;
-                let scale_y = f32::read_from(f, Self::BYTE_ORDER)?;
-                let rotation = f32::read_from(f, Self::BYTE_ORDER)?;
-                let flip_x = bool::read_from(f, Self::BYTE_ORDER)?;
-                let flip_y = bool::read_from(f, Self::BYTE_ORDER)?;
-                let multiply_color = Color::read_from(f, Self::BYTE_ORDER)?;
-                let screen_color = Color::read_from(f, Self::BYTE_ORDER)?;
-                let opacity = u8::read_from(f, Self::BYTE_ORDER)?;
-                let mut unk1 = [0; 12];
-                f.read_exact(&mut unk1)?;
-                let designation_id = u8::read_from(f, Self::BYTE_ORDER)?;
-                // i messed around with the ordering so one of these padding bytes is lost forever. rip.
-                let unk2 = u16::read_from(f, Self::BYTE_ORDER)? as u8;
-                let depth = StereoDepth {
-                    top_left: f32::read_from(f, Self::BYTE_ORDER)?,
-                    bottom_left: f32::read_from(f, Self::BYTE_ORDER)?,
-                    top_right: f32::read_from(f, Self::BYTE_ORDER)?,
-                    bottom_right: f32::read_from(f, Self::BYTE_ORDER)?,
-                };
-                parts.push(SpritePart {
-                    texture_pos,
-                    pos_x,
-                    pos_y,
-                    scale_x,
-                    scale_y,
-                    rotation,
-                    flip_x,
-                    flip_y,
-                    multiply_color,
-                    screen_color,
-                    opacity,
-                    unk1,
-                    designation_id,
-                    unk2,
-                    depth,
-                })
-            }
-            sprites.push(Sprite { parts });
-        }
-
-        let anim_count = u32::read_from(f, Self::BYTE_ORDER)?;
-        let mut animations = vec![];
-        for _ in 0..anim_count {
-            let name = VarLenString::read_from(f, Self::BYTE_ORDER)?.0.clone();
-            let interpolation = i32::read_from(f, Self::BYTE_ORDER)?;
-            let step_count = u32::read_from(f, Self::BYTE_ORDER)?;
-            let mut steps = vec![];
-            for _ in 0..step_count {
-                let sprite = u16::read_from(f, Self::BYTE_ORDER)?;
-                let duration = u16::read_from(f, Self::BYTE_ORDER)?;
-                let pos_x = i16::read_from(f, Self::BYTE_ORDER)?;
-                let pos_y = i16::read_from(f, Self::BYTE_ORDER)?;
-                let depth = f32::read_from(f, Self::BYTE_ORDER)?;
-                let scale_x = f32::read_from(f, Self::BYTE_ORDER)?;
-                let scale_y = f32::read_from(f, Self::BYTE_ORDER)?;
-                let rotation = f32::read_from(f, Self::BYTE_ORDER)?;
-                let multiply_color = Color::read_from(f, Self::BYTE_ORDER)?;
-                let mut unk = [0; 3];
-                f.read_exact(&mut unk)?;
-                let opacity = u16::read_from(f, Self::BYTE_ORDER)?;
-                steps.push(AnimationStep {
-                    sprite,
-                    duration,
-                    pos_x,
-                    pos_y,
-                    depth,
-                    scale_x,
-                    scale_y,
-                    rotation,
-                    multiply_color,
-                    unk,
-                    opacity,
-                })
-            }
-            animations.push(Animation {
-                name,
-                interpolation,
-                steps,
-            })
-        }
-
-        let timestamp = match timestamp {
-            Self::TIMESTAMP => None,
-            _ => Some(timestamp),
-        };
-
-        Ok(Self {
-            timestamp,
-            texture_width,
-            texture_height,
-            sprites,
-            animations,
-        })
+        Self::read_layout(&mut LayoutReader::new(f, Self::BYTE_ORDER))
     }
     fn to_binary<F: Write>(&self, f: &mut F) -> Result<()> {
         self.timestamp
@@ -302,6 +366,130 @@ impl BXCAD for BCCAD {
 }
 
 impl BCCAD {
+    /// Lists every field of a binary BCCAD with its offset, raw bytes and value
+    pub fn dump<F: Read>(f: &mut F) -> Result<Dump> {
+        let mut reader = LayoutReader::dumping(f, Self::BYTE_ORDER);
+        Self::read_layout(&mut reader)?;
+        reader.read_terminator()?;
+        reader.finish()
+    }
+
+    /// Layout of a binary BCCAD, for both [BXCAD::from_binary] and [BCCAD::dump]
+    fn read_layout<F: Read>(r: &mut LayoutReader<F>) -> Result<Self> {
+        let timestamp: u32 = r.read("timestamp")?;
+        let texture_width = r.read("texture_width")?;
+        let texture_height = r.read("texture_height")?;
+
+        let sprite_count: u32 = r.read("sprite_count")?;
+        let mut sprites = vec![];
+        for i in 0..sprite_count {
+            let sprite = r.scope(format_args!("sprites[{}]", i), |r| {
+                let parts_count: u32 = r.read("parts_count")?;
+                let mut parts = vec![];
+                for i in 0..parts_count {
+                    parts.push(r.scope(format_args!("parts[{}]", i), |r| {
+                        let texture_pos = r.scope(format_args!("texture_pos"), |r| {
+                            Ok(PosInTexture {
+                                x: r.read("x")?,
+                                y: r.read("y")?,
+                                width: r.read("width")?,
+                                height: r.read("height")?,
+                            })
+                        })?;
+                        let pos_x = r.read("pos_x")?;
+                        let pos_y = r.read("pos_y")?;
+                        let scale_x = r.read("scale_x")?;
+                        let scale_y = r.read("scale_y")?;
+                        let rotation = r.read("rotation")?;
+                        let flip_x = r.read("flip_x")?;
+                        let flip_y = r.read("flip_y")?;
+                        let multiply_color = r.read("multiply_color")?;
+                        let screen_color = r.read("screen_color")?;
+                        let opacity = r.read("opacity")?;
+                        let unk1 = r.read("unk1")?;
+                        let designation_id = r.read("designation_id")?;
+                        // i messed around with the ordering so one of these padding bytes is lost forever. rip.
+                        let unk2 = r.read::<u16>("unk2")? as u8;
+                        let depth = r.scope(format_args!("depth"), |r| {
+                            Ok(StereoDepth {
+                                top_left: r.read("top_left")?,
+                                bottom_left: r.read("bottom_left")?,
+                                top_right: r.read("top_right")?,
+                                bottom_right: r.read("bottom_right")?,
+                            })
+                        })?;
+                        Ok(SpritePart {
+                            texture_pos,
+                            pos_x,
+                            pos_y,
+                            scale_x,
+                            scale_y,
+                            rotation,
+                            flip_x,
+                            flip_y,
+                            multiply_color,
+                            screen_color,
+                            opacity,
+                            unk1,
+                            designation_id,
+                            unk2,
+                            depth,
+                        })
+                    })?);
+                }
+                Ok(Sprite { name: None, parts })
+            })?;
+            sprites.push(sprite);
+        }
+
+        let anim_count: u32 = r.read("animation_count")?;
+        let mut animations = vec![];
+        for i in 0..anim_count {
+            let animation = r.scope(format_args!("animations[{}]", i), |r| {
+                let name = r.read::<VarLenString>("name")?.0;
+                let interpolation = r.read("interpolation")?;
+                let step_count: u32 = r.read("step_count")?;
+                let mut steps = vec![];
+                for i in 0..step_count {
+                    steps.push(r.scope(format_args!("steps[{}]", i), |r| {
+                        Ok(AnimationStep {
+                            sprite: r.read("sprite")?,
+                            duration: r.read("duration")?,
+                            pos_x: r.read("pos_x")?,
+                            pos_y: r.read("pos_y")?,
+                            depth: r.read("depth")?,
+                            scale_x: r.read("scale_x")?,
+                            scale_y: r.read("scale_y")?,
+                            rotation: r.read("rotation")?,
+                            multiply_color: r.read("multiply_color")?,
+                            unk: r.read("unk")?,
+                            opacity: r.read("opacity")?,
+                        })
+                    })?);
+                }
+                Ok(Animation {
+                    name,
+                    interpolation,
+                    steps,
+                })
+            })?;
+            animations.push(animation);
+        }
+
+        let timestamp = match timestamp {
+            Self::TIMESTAMP => None,
+            _ => Some(timestamp),
+        };
+
+        Ok(Self {
+            timestamp,
+            texture_width,
+            texture_height,
+            sprites,
+            animations,
+        })
+    }
+
     #[deprecated(
         since = "2.0.0",
         note = "use `BCCAD::from_binary` (`BXCAD` trait) instead"
@@ -1,18 +1,23 @@
 use crate::{
     bxcad::{
-        serde_impl::{pos_xy, use_variation, variation_num},
+        dump::{Dump, LayoutReader},
+        serde_impl::{defaults, float, pos_xy, use_variation, variation_num},
         BXCADType, PosInTexture, BXCAD,
     },
     bytestream_addon::ByteStream,
     error::{Error, Result},
 };
-use bytestream::{ByteOrder, StreamReader, StreamWriter};
+use bytestream::{ByteOrder, StreamWriter};
 use encoding_rs::SHIFT_JIS;
 use serde::{Deserialize, Serialize};
-use std::io::{Read, Seek, Write};
+use std::{
+    hash::{Hash, Hasher},
+    io::{Read, Seek, Write},
+};
 
 /// A representation of the contents of a BRCAD file
 #[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct BRCAD {
     /// Date of latest format revision, in YYYYMMDD format (decimal).
     /// Known timestamp is 20100312 (Mar 12 2010)
@@ -20,6 +25,13 @@ pub struct BRCAD {
     /// Boolean value that tests whether or not the associated texture sheet has variations.
     /// Use [`has_variations`] or [`has_variations_mut`] instead
     #[deprecated(since = "2.1.0", note = "use has_variations instead")]
+    #[cfg_attr(
+        feature = "schema",
+        schemars(
+            schema_with = "use_variation::schema",
+            description = "Whether the associated texture sheet has variations, like in Flock Step"
+        )
+    )]
     #[serde(rename = "has_variations", alias = "unk0", with = "use_variation")]
     pub unk0: u32,
     /// Number of the spritesheet to use in a specific
@@ -47,11 +59,7 @@ impl BRCAD {
     /// this value is overwritten by the game
     #[allow(deprecated)]
     pub fn has_variations(&self) -> bool {
-        if cfg!(target_endian = "big") {
-            self.unk0 as u8 == 0
-        } else {
-            (self.unk0 >> 24) as u8 == 0
-        }
+        use_variation::decode(self.unk0)
     }
 
     /// Get whether or not associated texture sheet has variations, like in Flock Step (mutable)
@@ -77,7 +85,16 @@ pub struct BRCADLabels(pub Vec<String>);
 /// A frame of a BRCAD animation, composed of several [`SpritePart`]s or cells
 /// aligned together to create a full picture
 #[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(
+    feature = "schema",
+    derive(schemars::JsonSchema),
+    schemars(rename = "BRCADSprite")
+)]
 pub struct Sprite {
+    /// Name of the sprite, so that [`AnimationStep`]s can refer to it by name in indexized
+    /// JSON files. It's not stored in the BRCAD file itself
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
     /// Padding
     pub unk: u16,
     /// [`SpritePart`]s that form this Sprite
@@ -87,29 +104,57 @@ pub struct Sprite {
 /// A small image taken directly from the texture sheet, which grouped with others
 /// creates a full frame of the animation, that is, a [`Sprite`]
 #[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(
+    feature = "schema",
+    derive(schemars::JsonSchema),
+    schemars(rename = "BRCADSpritePart")
+)]
 pub struct SpritePart {
     /// Struct that defines the bounds of the SpritePart in the texture itself
     pub texture_pos: PosInTexture,
     /// Selects which variation to use.
     /// Use [`variation_num`] or [`variation_num_mut`] instead
     #[deprecated(since = "2.1.0", note = "use variation_num instead")]
-    #[serde(rename = "variation_num", alias = "unk", with = "variation_num")]
+    #[cfg_attr(
+        feature = "schema",
+        schemars(
+            schema_with = "variation_num::schema",
+            description = "Which variation of the texture sheet to use"
+        )
+    )]
+    #[serde(
+        default,
+        rename = "variation_num",
+        alias = "unk",
+        with = "variation_num"
+    )]
     pub unk: u32,
     /// X position where the part should be placed relative to the sprite
+    #[serde(default)]
     pub pos_x: u16,
     /// Y position where the part should be placed relative to the sprite
+    #[serde(default)]
     pub pos_y: u16,
     /// Scaling factor for the X axis
+    #[cfg_attr(feature = "schema", schemars(schema_with = "float::schema"))]
+    #[serde(default = "defaults::scale", with = "float")]
     pub scale_x: f32,
     /// Scaling factor for the Y axis
+    #[cfg_attr(feature = "schema", schemars(schema_with = "float::schema"))]
+    #[serde(default = "defaults::scale", with = "float")]
     pub scale_y: f32,
     /// Part rotation in degrees
+    #[cfg_attr(feature = "schema", schemars(schema_with = "float::schema"))]
+    #[serde(default, with = "float")]
     pub rotation: f32,
     /// Whether to flip the part on the X axis
+    #[serde(default)]
     pub flip_x: bool,
     /// Whether to flip the part on the Y axis
+    #[serde(default)]
     pub flip_y: bool,
     /// Opacity for the part
+    #[serde(default = "defaults::opacity::<u8>")]
     pub opacity: u8,
 }
 
@@ -141,9 +186,66 @@ impl SpritePart {
     }
 }
 
+// names aren't stored in the BRCAD file, so they don't make sprites different
+impl PartialEq for Sprite {
+    fn eq(&self, other: &Self) -> bool {
+        self.unk == other.unk && self.parts == other.parts
+    }
+}
+
+impl Eq for Sprite {}
+
+impl Hash for Sprite {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.unk.hash(state);
+        self.parts.hash(state);
+    }
+}
+
+#[allow(deprecated)]
+impl PartialEq for SpritePart {
+    fn eq(&self, other: &Self) -> bool {
+        self.texture_pos == other.texture_pos
+            && self.unk == other.unk
+            && self.pos_x == other.pos_x
+            && self.pos_y == other.pos_y
+            && float::bits(self.scale_x) == float::bits(other.scale_x)
+            && float::bits(self.scale_y) == float::bits(other.scale_y)
+            && float::bits(self.rotation) == float::bits(other.rotation)
+            && self.flip_x == other.flip_x
+            && self.flip_y == other.flip_y
+            && self.opacity == other.opacity
+    }
+}
+
+impl Eq for SpritePart {}
+
+#[allow(deprecated)]
+impl Hash for SpritePart {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.texture_pos.hash(state);
+        self.unk.hash(state);
+        self.pos_x.hash(state);
+        self.pos_y.hash(state);
+        float::bits(self.scale_x).hash(state);
+        float::bits(self.scale_y).hash(state);
+        float::bits(self.rotation).hash(state);
+        self.flip_x.hash(state);
+        self.flip_y.hash(state);
+        self.opacity.hash(state);
+    }
+}
+
 /// A cell animation for BRCAD, composed of different frames/[`Sprite`]s
+///
+/// `S` is the type used to refer to sprites, see [`AnimationStep`]
 #[derive(Serialize, Deserialize, Clone)]
-pub struct Animation {
+#[cfg_attr(
+    feature = "schema",
+    derive(schemars::JsonSchema),
+    schemars(rename = "BRCADAnimation_for_{S}")
+)]
+pub struct Animation<S = u16> {
     /// The name of the animation. Defined in the labels file, this may be
     /// missing from the struct if said file is not provided. **Do NOT change
     /// the order of the animations, the name is just there for development
@@ -152,31 +254,54 @@ pub struct Animation {
     /// Padding
     pub unk: u16,
     /// List of [`AnimationStep`]s that constitute this Animation
-    pub steps: Vec<AnimationStep>,
+    pub steps: Vec<AnimationStep<S>>,
 }
 
 /// These constitute an [`Animation`], and are a reference to
 /// a [`Sprite`] plus more information about it relative to the
 /// whole animation
+///
+/// `S` is the type used to refer to the sprite: its index number in the BRCAD
+/// by default, or a [`SpriteRef`](crate::bxcad::qol::SpriteRef) in indexized BRCADs
 #[derive(Serialize, Deserialize, Clone)]
-pub struct AnimationStep {
+#[cfg_attr(
+    feature = "schema",
+    derive(schemars::JsonSchema),
+    schemars(rename = "BRCADAnimationStep_for_{S}")
+)]
+pub struct AnimationStep<S = u16> {
     /// A reference to the index number of the [Sprite] this AnimationStep uses
-    pub sprite: u16,
+    pub sprite: S,
     /// Duration of the step (FPS is variable)
     pub duration: u16,
     /// Turns out this is actually *two* values: the X and Y displacement for the sprite.
     /// You should instead use [AnimationStep::pos_x] and [AnimationStep::pos_y], or the `_mut` variants
     #[deprecated(since = "2.1.0", note = "use pos_x and pos_y instead")]
-    #[serde(with = "pos_xy", rename = "pos", alias = "unk0")]
+    #[cfg_attr(
+        feature = "schema",
+        schemars(
+            schema_with = "pos_xy::schema",
+            description = "X and Y position the sprite is rendered to, relative to the animation"
+        )
+    )]
+    #[serde(default, with = "pos_xy", rename = "pos", alias = "unk0")]
     pub unk0: u32,
     /// Scaling factor for the X axis
+    #[cfg_attr(feature = "schema", schemars(schema_with = "float::schema"))]
+    #[serde(default = "defaults::scale", with = "float")]
     pub scale_x: f32,
     /// Scaling factor for the Y axis
+    #[cfg_attr(feature = "schema", schemars(schema_with = "float::schema"))]
+    #[serde(default = "defaults::scale", with = "float")]
     pub scale_y: f32,
     /// Rotation in degrees
+    #[cfg_attr(feature = "schema", schemars(schema_with = "float::schema"))]
+    #[serde(default, with = "float")]
     pub rotation: f32,
     /// Opacity for the sprite
+    #[serde(default = "defaults::opacity::<u8>")]
     pub opacity: u8,
+    #[serde(default)]
     pub unk1: [u8; 3],
 }
 
@@ -203,29 +328,46 @@ impl AnimationStep {
             unsafe { &mut *ptr }
         }
     }
+}
+
+impl<S> AnimationStep<S> {
+    /// Returns this same step, but referring to its sprite with the given value
+    pub fn with_sprite<T>(self, sprite: T) -> AnimationStep<T> {
+        #[allow(deprecated)]
+        AnimationStep {
+            sprite,
+            duration: self.duration,
+            unk0: self.unk0,
+            scale_x: self.scale_x,
+            scale_y: self.scale_y,
+            rotation: self.rotation,
+            opacity: self.opacity,
+            unk1: self.unk1,
+        }
+    }
 
     /// Get the X position for this step's sprite
     pub fn pos_x(&self) -> i16 {
         #[allow(deprecated)]
-        Self::get_pos(&self.unk0, false)
+        AnimationStep::get_pos(&self.unk0, false)
     }
 
     /// Get the Y position for this step's sprite
     pub fn pos_y(&self) -> i16 {
         #[allow(deprecated)]
-        Self::get_pos(&self.unk0, true)
+        AnimationStep::get_pos(&self.unk0, true)
     }
 
     /// Get the X position for this step's sprite (mutable)
     pub fn pos_x_mut(&mut self) -> &mut i16 {
         #[allow(deprecated)]
-        Self::get_pos_mut(&mut self.unk0, false)
+        AnimationStep::get_pos_mut(&mut self.unk0, false)
     }
 
     /// Get the Y position for this step's sprite (mutable)
     pub fn pos_y_mut(&mut self) -> &mut i16 {
         #[allow(deprecated)]
-        Self::get_pos_mut(&mut self.unk0, true)
+        AnimationStep::get_pos_mut(&mut self.unk0, true)
     }
 }
 
@@ -234,108 +376,7 @@ impl BXCAD for BRCAD {
     const TIMESTAMP: u32 = 20100312;
     const BXCAD_TYPE: BXCADType = BXCADType::BRCAD;
     fn from_binary<F: Read + Seek>(f: &mut F) -> Result<Self> {
-        let timestamp = u32::read_from(f, Self::BYTE_ORDER)?;
-        let unk0 = u32::read_from(f, Self::BYTE_ORDER)?;
-        let spritesheet_num = u16::read_from(f, Self::BYTE_ORDER)?;
-        let spritesheet_control = u16::read_from(f, Self::BYTE_ORDER)?;
-        let texture_width = u16::read_from(f, Self::BYTE_ORDER)?;
-        let texture_height = u16::read_from(f, Self::BYTE_ORDER)?;
-
-        let sprite_count = u16::read_from(f, Self::BYTE_ORDER)?;
-        let unk1 = u16::read_from(f, Self::BYTE_ORDER)?; //unknown
-        let mut sprites = vec![];
-        for _ in 0..sprite_count {
-            let parts_count = u16::read_from(f, Self::BYTE_ORDER)?;
-            let unk = u16::read_from(f, Self::BYTE_ORDER)?; //unknown
-            let mut parts = vec![];
-            for _ in 0..parts_count {
-                let texture_pos = PosInTexture {
-                    x: u16::read_from(f, Self::BYTE_ORDER)?,
-                    y: u16::read_from(f, Self::BYTE_ORDER)?,
-                    width: u16::read_from(f, Self::BYTE_ORDER)?,
-                    height: u16::read_from(f, Self::BYTE_ORDER)?,
-                };
-                let unk = u32::read_from(f, Self::BYTE_ORDER)?;
-                let pos_x = u16::read_from(f, Self::BYTE_ORDER)?;
-                let pos_y = u16::read_from(f, Self::BYTE_ORDER)?;
-                let scale_x = f32::read_from(f, Self::BYTE_ORDER)?;
-                let scale_y = f32::read_from(f, Self::BYTE_ORDER)?;
-                let rotation = f32::read_from(f, Self::BYTE_ORDER)?;
-                let flip_x = bool::read_from(f, Self::BYTE_ORDER)?;
-                let flip_y = bool::read_from(f, Self::BYTE_ORDER)?;
-                let opacity = u8::read_from(f, Self::BYTE_ORDER)?;
-                u8::read_from(f, Self::BYTE_ORDER)?; // terminator/padding
-                #[allow(deprecated)]
-                parts.push(SpritePart {
-                    texture_pos,
-                    unk,
-                    pos_x,
-                    pos_y,
-                    scale_x,
-                    scale_y,
-                    rotation,
-                    flip_x,
-                    flip_y,
-                    opacity,
-                })
-            }
-            sprites.push(Sprite { unk, parts });
-        }
-        let animation_count = u16::read_from(f, Self::BYTE_ORDER)?;
-        let unk2 = u16::read_from(f, Self::BYTE_ORDER)?; //unknown
-        let mut animations = vec![];
-        for _ in 0..animation_count {
-            let step_count = u16::read_from(f, Self::BYTE_ORDER)?;
-            let unk = u16::read_from(f, Self::BYTE_ORDER)?; //unknown
-            let mut steps = vec![];
-            for _ in 0..step_count {
-                let sprite = u16::read_from(f, Self::BYTE_ORDER)?;
-                let duration = u16::read_from(f, Self::BYTE_ORDER)?;
-                let unk0 = u32::read_from(f, Self::BYTE_ORDER)?;
-                let scale_x = f32::read_from(f, Self::BYTE_ORDER)?;
-                let scale_y = f32::read_from(f, Self::BYTE_ORDER)?;
-                let rotation = f32::read_from(f, Self::BYTE_ORDER)?;
-                let opacity = u8::read_from(f, Self::BYTE_ORDER)?;
-                let mut unk1 = [0u8; 3];
-                f.read_exact(&mut unk1)?;
-
-                #[allow(deprecated)]
-                steps.push(AnimationStep {
-                    sprite,
-                    duration,
-                    unk0,
-                    scale_x,
-                    scale_y,
-                    rotation,
-                    opacity,
-                    unk1,
-                });
-            }
-
-            animations.push(Animation {
-                name: None,
-                unk,
-                steps,
-            });
-        }
-        let timestamp = match timestamp {
-            Self::TIMESTAMP => None,
-            _ => Some(timestamp),
-        };
-
-        #[allow(deprecated)]
-        Ok(BRCAD {
-            timestamp,
-            unk0,
-            spritesheet_num,
-            spritesheet_control,
-            texture_width,
-            texture_height,
-            unk1,
-            sprites,
-            unk2,
-            animations,
-        })
+        Self::read_layout(&mut LayoutReader::new(f, Self::BYTE_ORDER))
     }
     fn to_binary<F: Write>(&self, f: &mut F) -> Result<()> {
         self.timestamp
@@ -394,6 +435,136 @@ impl BXCAD for BRCAD {
 }
 
 impl BRCAD {
+    /// Lists every field of a binary BRCAD with its offset, raw bytes and value
+    pub fn dump<F: Read>(f: &mut F) -> Result<Dump> {
+        let mut reader = LayoutReader::dumping(f, Self::BYTE_ORDER);
+        Self::read_layout(&mut reader)?;
+        reader.finish()
+    }
+
+    /// Layout of a binary BRCAD, for both [BXCAD::from_binary] and [BRCAD::dump]
+    fn read_layout<F: Read>(r: &mut LayoutReader<F>) -> Result<Self> {
+        let timestamp: u32 = r.read("timestamp")?;
+        let unk0 = r.read_as("has_variations", |c: &u32| {
+            format!("{} (raw {})", use_variation::decode(*c), raw(*c))
+        })?;
+        let spritesheet_num = r.read("spritesheet_num")?;
+        let spritesheet_control = r.read("spritesheet_control")?;
+        let texture_width = r.read("texture_width")?;
+        let texture_height = r.read("texture_height")?;
+
+        let sprite_count: u16 = r.read("sprite_count")?;
+        let unk1 = r.read("unk1")?; //unknown
+        let mut sprites = vec![];
+        for i in 0..sprite_count {
+            let sprite = r.scope(format_args!("sprites[{}]", i), |r| {
+                let parts_count: u16 = r.read("parts_count")?;
+                let unk = r.read("unk")?; //unknown
+                let mut parts = vec![];
+                for i in 0..parts_count {
+                    parts.push(r.scope(format_args!("parts[{}]", i), |r| {
+                        let texture_pos = r.scope(format_args!("texture_pos"), |r| {
+                            Ok(PosInTexture {
+                                x: r.read("x")?,
+                                y: r.read("y")?,
+                                width: r.read("width")?,
+                                height: r.read("height")?,
+                            })
+                        })?;
+                        let unk = r.read_as("variation_num", |c: &u32| {
+                            format!("{} (raw {})", variation_num::decode(*c), raw(*c))
+                        })?;
+                        let pos_x = r.read("pos_x")?;
+                        let pos_y = r.read("pos_y")?;
+                        let scale_x = r.read("scale_x")?;
+                        let scale_y = r.read("scale_y")?;
+                        let rotation = r.read("rotation")?;
+                        let flip_x = r.read("flip_x")?;
+                        let flip_y = r.read("flip_y")?;
+                        let opacity = r.read("opacity")?;
+                        r.read::<u8>("(padding)")?; // terminator/padding
+                        #[allow(deprecated)]
+                        Ok(SpritePart {
+                            texture_pos,
+                            unk,
+                            pos_x,
+                            pos_y,
+                            scale_x,
+                            scale_y,
+                            rotation,
+                            flip_x,
+                            flip_y,
+                            opacity,
+                        })
+                    })?);
+                }
+                Ok(Sprite {
+                    name: None,
+                    unk,
+                    parts,
+                })
+            })?;
+            sprites.push(sprite);
+        }
+
+        let animation_count: u16 = r.read("animation_count")?;
+        let unk2 = r.read("unk2")?; //unknown
+        let mut animations = vec![];
+        for i in 0..animation_count {
+            let animation = r.scope(format_args!("animations[{}]", i), |r| {
+                let step_count: u16 = r.read("step_count")?;
+                let unk = r.read("unk")?; //unknown
+                let mut steps = vec![];
+                for i in 0..step_count {
+                    steps.push(r.scope(format_args!("steps[{}]", i), |r| {
+                        #[allow(deprecated)]
+                        Ok(AnimationStep {
+                            sprite: r.read("sprite")?,
+                            duration: r.read("duration")?,
+                            unk0: r.read_as("pos", |c: &u32| {
+                                let (x, y) = (
+                                    AnimationStep::get_pos(c, false),
+                                    AnimationStep::get_pos(c, true),
+                                );
+                                format!("[{}, {}] (raw {})", x, y, raw(*c))
+                            })?,
+                            scale_x: r.read("scale_x")?,
+                            scale_y: r.read("scale_y")?,
+                            rotation: r.read("rotation")?,
+                            opacity: r.read("opacity")?,
+                            unk1: r.read("unk1")?,
+                        })
+                    })?);
+                }
+                Ok(Animation {
+                    name: None,
+                    unk,
+                    steps,
+                })
+            })?;
+            animations.push(animation);
+        }
+
+        let timestamp = match timestamp {
+            Self::TIMESTAMP => None,
+            _ => Some(timestamp),
+        };
+
+        #[allow(deprecated)]
+        Ok(BRCAD {
+            timestamp,
+            unk0,
+            spritesheet_num,
+            spritesheet_control,
+            texture_width,
+            texture_height,
+            unk1,
+            sprites,
+            unk2,
+            animations,
+        })
+    }
+
     /// Uses the contents of the associated labels file to add names to the struct
     pub fn apply_labels<F: Read>(&mut self, labels: &mut F) -> Result<()> {
         let mut data = vec![];
@@ -438,3 +609,8 @@ impl BRCAD {
         Ok(())
     }
 }
+
+/// A field that flour JSON splits into other values, as shown in a [Dump]
+fn raw(value: u32) -> String {
+    format!("{:#010x}", value)
+}
@@ -0,0 +1,231 @@
+use std::fmt;
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::{error::Result, BXCAD};
+
+/// A single difference between two BXCADs
+#[derive(Clone, Debug, PartialEq)]
+pub struct Difference {
+    /// Location of the value that changed, like `animations["beat"].steps[2].duration`
+    pub path: String,
+    /// What changed in that location
+    pub change: Change,
+}
+
+/// The kind of change found by [diff], with the values involved as they'd appear in flour JSON
+#[derive(Clone, Debug, PartialEq)]
+pub enum Change {
+    /// The value only exists in the new BXCAD
+    Added(Value),
+    /// The value only exists in the old BXCAD
+    Removed(Value),
+    /// The value is different between both BXCADs
+    Changed(Value, Value),
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.change {
+            Change::Added(_) => write!(f, "{}: added", self.path),
+            Change::Removed(_) => write!(f, "{}: removed", self.path),
+            Change::Changed(old, new) => write!(f, "{}: {} -> {}", self.path, old, new),
+        }
+    }
+}
+
+/// Compares two BXCADs field by field, and returns every difference between them
+///
+/// Sprites, parts and steps are compared by their position in their list. Animations are compared by
+/// their name instead, as long as every animation has a different one, so that adding or reordering
+/// animations doesn't show up as changes in all of the animations after it.
+pub fn diff<X: BXCAD + Serialize>(old: &X, new: &X) -> Result<Vec<Difference>> {
+    let old = serde_json::to_value(old)?;
+    let new = serde_json::to_value(new)?;
+    let mut out = vec![];
+    diff_value(&mut out, String::new(), &old, &new);
+    Ok(out)
+}
+
+fn diff_value(out: &mut Vec<Difference>, path: String, old: &Value, new: &Value) {
+    match (old, new) {
+        (Value::Object(old), Value::Object(new)) => diff_object(out, &path, old, new),
+        (Value::Array(old), Value::Array(new)) => {
+            match (named_elements(&path, old), named_elements(&path, new)) {
+                (Some(old), Some(new)) => diff_named(out, &path, &old, &new),
+                _ => diff_array(out, &path, old, new),
+            }
+        }
+        _ if old != new => out.push(Difference {
+            path,
+            change: Change::Changed(old.clone(), new.clone()),
+        }),
+        _ => (),
+    }
+}
+
+fn diff_object(
+    out: &mut Vec<Difference>,
+    path: &str,
+    old: &Map<String, Value>,
+    new: &Map<String, Value>,
+) {
+    let field_path = |key: &str| {
+        if path.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", path, key)
+        }
+    };
+    for (key, old_value) in old {
+        match new.get(key) {
+            Some(new_value) => diff_value(out, field_path(key), old_value, new_value),
+            None => out.push(Difference {
+                path: field_path(key),
+                change: Change::Removed(old_value.clone()),
+            }),
+        }
+    }
+    for (key, new_value) in new {
+        if !old.contains_key(key) {
+            out.push(Difference {
+                path: field_path(key),
+                change: Change::Added(new_value.clone()),
+            });
+        }
+    }
+}
+
+fn diff_array(out: &mut Vec<Difference>, path: &str, old: &[Value], new: &[Value]) {
+    for (i, old_value) in old.iter().enumerate() {
+        let path = format!("{}[{}]", path, i);
+        match new.get(i) {
+            Some(new_value) => diff_value(out, path, old_value, new_value),
+            None => out.push(Difference {
+                path,
+                change: Change::Removed(old_value.clone()),
+            }),
+        }
+    }
+    for (i, new_value) in new.iter().enumerate().skip(old.len()) {
+        out.push(Difference {
+            path: format!("{}[{}]", path, i),
+            change: Change::Added(new_value.clone()),
+        });
+    }
+}
+
+fn diff_named(
+    out: &mut Vec<Difference>,
+    path: &str,
+    old: &[(&str, &Value)],
+    new: &[(&str, &Value)],
+) {
+    let name_path = |name: &str| format!("{}[{}]", path, Value::from(name));
+    for (name, old_value) in old {
+        match new.iter().find(|(c, _)| c == name) {
+            Some((_, new_value)) => diff_value(out, name_path(name), old_value, new_value),
+            None => out.push(Difference {
+                path: name_path(name),
+                change: Change::Removed((*old_value).clone()),
+            }),
+        }
+    }
+    for (name, new_value) in new {
+        if !old.iter().any(|(c, _)| c == name) {
+            out.push(Difference {
+                path: name_path(name),
+                change: Change::Added((*new_value).clone()),
+            });
+        }
+    }
+}
+
+/// If the array is a list of animations that all have different names, returns them along with their names
+pub(crate) fn named_elements<'a>(
+    path: &str,
+    array: &'a [Value],
+) -> Option<Vec<(&'a str, &'a Value)>> {
+    if path != "animations" {
+        return None;
+    }
+    let mut out: Vec<(&str, &Value)> = vec![];
+    for value in array {
+        let name = value.get("name")?.as_str()?;
+        if out.iter().any(|(c, _)| *c == name) {
+            return None;
+        }
+        out.push((name, value));
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BCCAD;
+    use std::io::Cursor;
+
+    fn sample() -> BCCAD {
+        BCCAD::from_binary(&mut Cursor::new(include_bytes!(
+            "../../tests/fixtures/sample.bccad"
+        )))
+        .unwrap()
+    }
+
+    fn diff_lines(old: &BCCAD, new: &BCCAD) -> Vec<String> {
+        diff(old, new)
+            .unwrap()
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn changed_field() {
+        let old = sample();
+        let mut new = sample();
+        new.animations[0].steps[0].duration = 5;
+        new.texture_width = 1024;
+        assert_eq!(
+            diff_lines(&old, &new),
+            [
+                "texture_width: 512 -> 1024",
+                "animations[\"beat\"].steps[0].duration: 4 -> 5",
+            ]
+        );
+    }
+
+    #[test]
+    fn added_and_removed_sprites() {
+        let old = sample();
+        let mut new = sample();
+        let removed = new.sprites.pop().unwrap();
+        assert_eq!(diff_lines(&old, &new), ["sprites[3]: removed"]);
+        assert_eq!(diff_lines(&new, &old), ["sprites[3]: added"]);
+
+        let difference = &diff(&new, &old).unwrap()[0];
+        assert_eq!(
+            difference.change,
+            Change::Added(serde_json::to_value(&removed).unwrap())
+        );
+    }
+
+    #[test]
+    fn animations_are_matched_by_name() {
+        let old = sample();
+        let mut new = sample();
+        new.animations.reverse();
+        assert_eq!(diff_lines(&old, &new), Vec::<String>::new());
+
+        new.animations[0].name = "rest".to_string();
+        assert_eq!(
+            diff_lines(&old, &new),
+            [
+                "animations[\"idle\"]: removed",
+                "animations[\"rest\"]: added"
+            ]
+        );
+    }
+}
@@ -0,0 +1,293 @@
+use bytestream::{ByteOrder, StreamReader};
+use std::{
+    fmt::{self, Display},
+    io::Read,
+};
+
+use crate::{bytestream_addon::ByteStream, error::Result, Color, VarLenString};
+
+/// Number of raw bytes shown in each line of a [Dump]
+const BYTES_PER_LINE: usize = 12;
+
+/// A field of a binary BXCAD, as found in a [Dump]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DumpEntry {
+    /// Position of the field in the file
+    pub offset: u64,
+    /// Raw bytes of the field
+    pub bytes: Vec<u8>,
+    /// Path of the field, like `sprites[2].parts[0].scale_x`, with the same names as in flour
+    /// JSON. Fields that only exist in the binary file, like counts and padding, have names of
+    /// their own
+    pub path: String,
+    /// The value of the field, as read by flour
+    pub value: String,
+}
+
+/// Every field of a binary BXCAD in the order they're read, with their offset, raw bytes,
+/// path and value
+///
+/// Made by the same code that reads BXCADs, so it always matches what flour sees. Any bytes
+/// after the end of the BXCAD (and its terminator, for BCCADs) are listed at the end as
+/// `(trailing data)`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Dump(pub Vec<DumpEntry>);
+
+impl Display for Dump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = BYTES_PER_LINE * 3 - 1;
+        writeln!(f, "{:<8}  {:<width$}  field = value", "offset", "bytes")?;
+        for entry in &self.0 {
+            let mut lines = entry.bytes.chunks(BYTES_PER_LINE).map(|c| {
+                c.iter()
+                    .map(|c| format!("{:02x}", c))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            });
+            let first = lines.next().unwrap_or_default();
+            writeln!(
+                f,
+                "{:08x}  {:<width$}  {} = {}",
+                entry.offset, first, entry.path, entry.value
+            )?;
+            // long fields go on for several lines
+            for line in lines {
+                writeln!(f, "{:8}  {}", "", line)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A value that a [LayoutReader] can read on its own
+pub(crate) trait Field: Sized {
+    fn read_field<R: Read>(f: &mut R, order: ByteOrder) -> Result<Self>;
+    /// The value as shown in a [Dump]
+    fn describe(&self) -> String;
+}
+
+macro_rules! stream_field {
+    ($($t:ty),*) => {
+        $(
+            impl Field for $t {
+                fn read_field<R: Read>(f: &mut R, order: ByteOrder) -> Result<Self> {
+                    Ok(<$t as StreamReader>::read_from(f, order)?)
+                }
+                fn describe(&self) -> String {
+                    self.to_string()
+                }
+            }
+        )*
+    };
+}
+
+stream_field!(u8, u16, u32, i16, i32, bool);
+
+impl Field for f32 {
+    fn read_field<R: Read>(f: &mut R, order: ByteOrder) -> Result<Self> {
+        <f32 as ByteStream>::read_from(f, order)
+    }
+    fn describe(&self) -> String {
+        format!("{:?}", self)
+    }
+}
+
+impl Field for Color {
+    fn read_field<R: Read>(f: &mut R, order: ByteOrder) -> Result<Self> {
+        <Color as ByteStream>::read_from(f, order)
+    }
+    fn describe(&self) -> String {
+        self.to_hex()
+    }
+}
+
+impl Field for VarLenString {
+    fn read_field<R: Read>(f: &mut R, order: ByteOrder) -> Result<Self> {
+        <VarLenString as ByteStream>::read_from(f, order)
+    }
+    fn describe(&self) -> String {
+        format!("{:?}", self.0)
+    }
+}
+
+impl<const N: usize> Field for [u8; N] {
+    fn read_field<R: Read>(f: &mut R, _order: ByteOrder) -> Result<Self> {
+        let mut bytes = [0; N];
+        f.read_exact(&mut bytes)?;
+        Ok(bytes)
+    }
+    fn describe(&self) -> String {
+        format!("{:?}", self)
+    }
+}
+
+/// Reads the fields of a binary BXCAD one by one, keeping a [Dump] of them if asked to
+///
+/// This way, reading a BXCAD and dumping it share the same code.
+pub(crate) struct LayoutReader<'a, F> {
+    f: &'a mut F,
+    order: ByteOrder,
+    offset: u64,
+    /// Path to the fields being read
+    scopes: Vec<String>,
+    dump: Option<Dump>,
+}
+
+impl<'a, F: Read> LayoutReader<'a, F> {
+    pub fn new(f: &'a mut F, order: ByteOrder) -> Self {
+        Self {
+            f,
+            order,
+            offset: 0,
+            scopes: vec![],
+            dump: None,
+        }
+    }
+
+    /// Create a reader that keeps a [Dump] of everything it reads
+    pub fn dumping(f: &'a mut F, order: ByteOrder) -> Self {
+        Self {
+            dump: Some(Dump::default()),
+            ..Self::new(f, order)
+        }
+    }
+
+    /// Read the next field, with the given name
+    pub fn read<T: Field>(&mut self, name: &str) -> Result<T> {
+        self.read_as(name, T::describe)
+    }
+
+    /// Read the next field, with the given name, showing its value in the [Dump] some other way
+    /// than [Field::describe], like the way it's shown in flour JSON
+    pub fn read_as<T: Field>(
+        &mut self,
+        name: &str,
+        describe: impl FnOnce(&T) -> String,
+    ) -> Result<T> {
+        let Some(dump) = &mut self.dump else {
+            return T::read_field(self.f, self.order);
+        };
+        let mut tee = Tee {
+            f: self.f,
+            bytes: vec![],
+        };
+        let value = T::read_field(&mut tee, self.order)?;
+
+        let mut path = self.scopes.join(".");
+        if !path.is_empty() {
+            path.push('.');
+        }
+        path.push_str(name);
+        dump.0.push(DumpEntry {
+            offset: self.offset,
+            path,
+            value: describe(&value),
+            bytes: tee.bytes,
+        });
+        self.offset += dump.0.last().map_or(0, |c| c.bytes.len() as u64);
+        Ok(value)
+    }
+
+    /// Read fields that are inside of the given one, like the fields of a struct
+    ///
+    /// The name is only formatted when dumping, so that reading a BXCAD doesn't allocate it for
+    /// every sprite part and animation step
+    pub fn scope<T>(
+        &mut self,
+        name: fmt::Arguments,
+        read: impl FnOnce(&mut Self) -> Result<T>,
+    ) -> Result<T> {
+        let dumping = self.dump.is_some();
+        if dumping {
+            self.scopes.push(name.to_string());
+        }
+        let value = read(self)?;
+        if dumping {
+            self.scopes.pop();
+        }
+        Ok(value)
+    }
+
+    /// Read the byte some BXCADs end with, which isn't needed to read them, if it's there
+    pub fn read_terminator(&mut self) -> Result<()> {
+        let mut byte = [0];
+        if self.f.read(&mut byte)? == 0 {
+            return Ok(());
+        }
+        if let Some(dump) = &mut self.dump {
+            dump.0.push(DumpEntry {
+                offset: self.offset,
+                bytes: byte.to_vec(),
+                path: "(terminator)".to_string(),
+                value: byte[0].to_string(),
+            });
+        }
+        self.offset += 1;
+        Ok(())
+    }
+
+    /// Get the dump of everything that was read, plus whatever is left in the file
+    pub fn finish(self) -> Result<Dump> {
+        let mut dump = self.dump.unwrap_or_default();
+        let mut trailing = vec![];
+        self.f.read_to_end(&mut trailing)?;
+        if !trailing.is_empty() {
+            dump.0.push(DumpEntry {
+                offset: self.offset,
+                value: match trailing.len() {
+                    1 => "1 byte".to_string(),
+                    c => format!("{} bytes", c),
+                },
+                bytes: trailing,
+                path: "(trailing data)".to_string(),
+            });
+        }
+        Ok(dump)
+    }
+}
+
+/// Reader that keeps a copy of the bytes read through it
+struct Tee<'a, F> {
+    f: &'a mut F,
+    bytes: Vec<u8>,
+}
+
+impl<F: Read> Read for Tee<'_, F> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.f.read(buf)?;
+        self.bytes.extend_from_slice(&buf[..read]);
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{BCCAD, BRCAD};
+    use std::io::Cursor;
+
+    #[test]
+    fn bccad_terminator_is_labeled() {
+        let bytes = include_bytes!("../../tests/fixtures/sample.bccad");
+        let dump = BCCAD::dump(&mut Cursor::new(bytes)).unwrap();
+        let last = dump.0.last().unwrap();
+        assert_eq!(last.path, "(terminator)");
+        assert_eq!(last.offset as usize, bytes.len() - 1);
+    }
+
+    #[test]
+    fn brcad_split_fields_are_decoded() {
+        let bytes = include_bytes!("../../tests/fixtures/sample.brcad");
+        let dump = BRCAD::dump(&mut Cursor::new(bytes)).unwrap();
+        let value = |path: &str| &dump.0.iter().find(|c| c.path == path).unwrap().value;
+        assert_eq!(value("has_variations"), "false (raw 0x00000000)");
+        assert_eq!(
+            value("sprites[0].parts[0].variation_num"),
+            "0 (raw 0x00000000)"
+        );
+        assert_eq!(
+            value("animations[0].steps[0].pos"),
+            "[1, -2] (raw 0x0001fffe)"
+        );
+        assert!(dump.0.iter().all(|c| c.path != "(trailing data)"));
+    }
+}
@@ -0,0 +1,500 @@
+use std::{collections::HashMap, hash::Hash};
+
+use crate::{
+    bxcad::{bccad, brcad, PosInTexture},
+    error::{Error, Result},
+    BXCAD,
+};
+
+/// Trait for BXCAD types whose sprites can be edited without breaking the references to them
+/// in animation steps
+///
+/// Animation steps refer to sprites by their position in the sprite list, so inserting, removing or
+/// reordering sprites by hand means every one of those references has to be fixed too. The methods
+/// provided here do that automatically.
+pub trait Editable: BXCAD {
+    /// The sprite type of this BXCAD
+    type Sprite;
+    /// The animation type of this BXCAD
+    type Animation;
+
+    /// The list of sprites in this BXCAD
+    fn sprites(&self) -> &Vec<Self::Sprite>;
+    /// The list of sprites in this BXCAD (mutable)
+    fn sprites_mut(&mut self) -> &mut Vec<Self::Sprite>;
+    /// The list of animations in this BXCAD
+    fn animations(&self) -> &Vec<Self::Animation>;
+    /// The list of animations in this BXCAD (mutable)
+    fn animations_mut(&mut self) -> &mut Vec<Self::Animation>;
+    /// The name of the given animation, if it has one
+    fn animation_name(anim: &Self::Animation) -> Option<&str>;
+    /// The sprite index for every step of the given animation, in order
+    fn animation_sprites(anim: &Self::Animation) -> Vec<u16>;
+    /// The sprite index for every step of the given animation, in order (mutable)
+    fn animation_sprites_mut(anim: &mut Self::Animation) -> Vec<&mut u16>;
+    /// The texture regions used by each part of the given sprite
+    fn sprite_regions(sprite: &Self::Sprite) -> Vec<&PosInTexture>;
+    /// The texture regions used by each part of the given sprite (mutable)
+    fn sprite_regions_mut(sprite: &mut Self::Sprite) -> Vec<&mut PosInTexture>;
+
+    /// Every sprite reference in the animation steps of this BXCAD
+    fn sprite_refs_mut(&mut self) -> Vec<&mut u16> {
+        self.animations_mut()
+            .iter_mut()
+            .flat_map(Self::animation_sprites_mut)
+            .collect()
+    }
+
+    /// The sprite index for every step of every animation, in order
+    fn step_sprites(&self) -> Vec<Vec<u16>> {
+        self.animations()
+            .iter()
+            .map(Self::animation_sprites)
+            .collect()
+    }
+
+    /// Returns the index of the animation with the given name
+    ///
+    /// Animations without a name can also be found by their index, written as a decimal number
+    fn find_animation(&self, name: &str) -> Option<usize> {
+        let anims = self.animations();
+        match anims
+            .iter()
+            .position(|c| Self::animation_name(c) == Some(name))
+        {
+            Some(c) => Some(c),
+            None => match name.parse::<usize>() {
+                Ok(c) if c < anims.len() && Self::animation_name(&anims[c]).is_none() => Some(c),
+                _ => None,
+            },
+        }
+    }
+
+    /// Returns the position of every animation step that uses the given sprite, as pairs of
+    /// animation index and step index
+    fn sprite_users(&self, index: u16) -> Vec<(usize, usize)> {
+        let mut out = vec![];
+        for (i, anim) in self.step_sprites().iter().enumerate() {
+            for (j, sprite) in anim.iter().enumerate() {
+                if *sprite == index {
+                    out.push((i, j));
+                }
+            }
+        }
+        out
+    }
+
+    /// Inserts a sprite at the given position, shifting all sprites after it (and the references
+    /// to them)
+    fn insert_sprite(&mut self, index: u16, sprite: Self::Sprite) -> Result<()> {
+        if index as usize > self.sprites().len() {
+            Err(Error::SpriteOutOfRange(index))?
+        }
+        if self.sprites().len() > u16::MAX as usize {
+            Err(Error::TooManySprites)?
+        }
+        // invalid references get shifted too, so they might not fit anymore
+        let Some(shifted) = self
+            .step_sprites()
+            .into_iter()
+            .flatten()
+            .map(|c| {
+                if c >= index {
+                    c.checked_add(1)
+                } else {
+                    Some(c)
+                }
+            })
+            .collect::<Option<Vec<_>>>()
+        else {
+            Err(Error::TooManySprites)?
+        };
+        self.sprites_mut().insert(index as usize, sprite);
+        for (sprite, c) in self.sprite_refs_mut().into_iter().zip(shifted) {
+            *sprite = c;
+        }
+        Ok(())
+    }
+
+    /// Removes the sprite at the given position, shifting all sprites after it (and the references
+    /// to them)
+    ///
+    /// Fails if any animation step uses the sprite. See [Editable::sprite_users] to find out which ones
+    fn remove_sprite(&mut self, index: u16) -> Result<Self::Sprite> {
+        if index as usize >= self.sprites().len() {
+            Err(Error::SpriteOutOfRange(index))?
+        }
+        if !self.sprite_users(index).is_empty() {
+            Err(Error::SpriteInUse(index))?
+        }
+        let sprite = self.sprites_mut().remove(index as usize);
+        for sprite in self.sprite_refs_mut() {
+            if *sprite > index {
+                *sprite -= 1;
+            }
+        }
+        Ok(sprite)
+    }
+
+    /// Moves the sprite at position `from` so that it ends up at position `to`, shifting the sprites
+    /// in between (and the references to all of them)
+    fn move_sprite(&mut self, from: u16, to: u16) -> Result<()> {
+        let len = self.sprites().len();
+        for index in [from, to] {
+            if index as usize >= len {
+                Err(Error::SpriteOutOfRange(index))?
+            }
+        }
+        let sprite = self.sprites_mut().remove(from as usize);
+        self.sprites_mut().insert(to as usize, sprite);
+        for sprite in self.sprite_refs_mut() {
+            if *sprite == from {
+                *sprite = to;
+            } else if from < to && (from + 1..=to).contains(sprite) {
+                *sprite -= 1;
+            } else if to < from && (to..from).contains(sprite) {
+                *sprite = sprite.checked_add(1).ok_or(Error::TooManySprites)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Swaps the sprites at the given positions, along with the references to them
+    fn swap_sprites(&mut self, a: u16, b: u16) -> Result<()> {
+        let len = self.sprites().len();
+        for index in [a, b] {
+            if index as usize >= len {
+                Err(Error::SpriteOutOfRange(index))?
+            }
+        }
+        self.sprites_mut().swap(a as usize, b as usize);
+        for sprite in self.sprite_refs_mut() {
+            if *sprite == a {
+                *sprite = b;
+            } else if *sprite == b {
+                *sprite = a;
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes every sprite that isn't used by any animation step, and renumbers the rest of the
+    /// sprites (and the references to them) so that there's no gaps left
+    ///
+    /// Returns the removed sprites, along with the index they had before being removed
+    fn remove_unused_sprites(&mut self) -> Vec<(u16, Self::Sprite)> {
+        let len = self.sprites().len();
+        let mut used = vec![false; len];
+        for sprite in self.step_sprites().into_iter().flatten() {
+            if let Some(c) = used.get_mut(sprite as usize) {
+                *c = true;
+            }
+        }
+
+        let mut new_indexes = vec![0u16; len];
+        let mut removed = vec![];
+        let mut kept = vec![];
+        for (i, sprite) in self.sprites_mut().drain(..).enumerate() {
+            if used[i] {
+                new_indexes[i] = kept.len() as u16;
+                kept.push(sprite);
+            } else {
+                removed.push((i as u16, sprite));
+            }
+        }
+        *self.sprites_mut() = kept;
+
+        for sprite in self.sprite_refs_mut() {
+            match new_indexes.get(*sprite as usize) {
+                Some(c) => *sprite = *c,
+                // invalid references stay invalid, but they still get shifted
+                None => *sprite -= removed.len() as u16,
+            }
+        }
+        removed
+    }
+
+    /// Merges sprites that are exactly the same into the first one of them, and renumbers the rest of the
+    /// sprites (and the references to them) so that there's no gaps left
+    ///
+    /// Returns the removed sprites' original index, along with the new index of the sprite they were merged into
+    fn merge_duplicate_sprites(&mut self) -> Vec<(u16, u16)>
+    where
+        Self::Sprite: Eq + Hash,
+    {
+        let mut new_indexes = vec![];
+        let mut merged = vec![];
+        let mut keep = vec![];
+        let mut first_of = HashMap::new();
+        for (i, sprite) in self.sprites().iter().enumerate() {
+            match first_of.get(sprite) {
+                Some(c) => {
+                    new_indexes.push(*c);
+                    merged.push((i as u16, *c));
+                    keep.push(false);
+                }
+                None => {
+                    let index = first_of.len() as u16;
+                    first_of.insert(sprite, index);
+                    new_indexes.push(index);
+                    keep.push(true);
+                }
+            }
+        }
+        let mut keep = keep.into_iter();
+        self.sprites_mut().retain(|_| keep.next().unwrap_or(true));
+
+        for sprite in self.sprite_refs_mut() {
+            match new_indexes.get(*sprite as usize) {
+                Some(c) => *sprite = *c,
+                // invalid references stay invalid, but they still get shifted
+                None => *sprite -= merged.len() as u16,
+            }
+        }
+        merged
+    }
+
+    /// Copies the animations with the given names (see [Editable::find_animation]) from another BXCAD
+    /// into this one
+    ///
+    /// The sprites these animations use are copied along with them and added at the end of the sprite
+    /// list, with the animation steps' references changed to match.
+    fn import_animations(
+        &mut self,
+        other: &Self,
+        names: &[&str],
+        options: &ImportOptions,
+    ) -> Result<()>
+    where
+        Self::Sprite: Clone,
+        Self::Animation: Clone,
+    {
+        // nothing is changed until every animation is known to be fine to import
+        let mut new_indexes: HashMap<u16, u16> = HashMap::new();
+        let mut sprites = vec![];
+        let mut animations = self.animations().clone();
+        for name in names {
+            let Some(index) = other.find_animation(name) else {
+                Err(Error::AnimationNotFound(name.to_string()))?
+            };
+            let mut anim = other.animations()[index].clone();
+
+            for sprite in Self::animation_sprites_mut(&mut anim) {
+                let new_index = match new_indexes.get(sprite) {
+                    Some(c) => *c,
+                    None => {
+                        let Some(og) = other.sprites().get(*sprite as usize) else {
+                            Err(Error::SpriteOutOfRange(*sprite))?
+                        };
+                        let Ok(new_index) = u16::try_from(self.sprites().len() + sprites.len())
+                        else {
+                            Err(Error::TooManySprites)?
+                        };
+                        let mut og = og.clone();
+                        for region in Self::sprite_regions_mut(&mut og) {
+                            region.x = region.x.saturating_add(options.region_offset.0);
+                            region.y = region.y.saturating_add(options.region_offset.1);
+                        }
+                        sprites.push(og);
+                        new_indexes.insert(*sprite, new_index);
+                        new_index
+                    }
+                };
+                *sprite = new_index;
+            }
+
+            let existing = match Self::animation_name(&anim) {
+                Some(name) => animations
+                    .iter()
+                    .position(|c| Self::animation_name(c) == Some(name)),
+                None => None,
+            };
+            match existing {
+                Some(i) if options.replace => animations[i] = anim,
+                Some(_) => Err(Error::AnimationExists(name.to_string()))?,
+                None => animations.push(anim),
+            }
+        }
+
+        self.sprites_mut().extend(sprites);
+        *self.animations_mut() = animations;
+        Ok(())
+    }
+
+    /// Returns the texture regions used by the given sprites that aren't used by any sprite in this BXCAD,
+    /// for example the ones returned by [Editable::remove_unused_sprites]
+    fn unused_regions(&self, removed: &[(u16, Self::Sprite)]) -> Vec<PosInTexture> {
+        let key = |c: &PosInTexture| (c.x, c.y, c.width, c.height);
+        let used: Vec<_> = self
+            .sprites()
+            .iter()
+            .flat_map(|c| Self::sprite_regions(c))
+            .map(key)
+            .collect();
+
+        let mut out: Vec<PosInTexture> = vec![];
+        for region in removed.iter().flat_map(|(_, c)| Self::sprite_regions(c)) {
+            if !used.contains(&key(region)) && !out.iter().any(|c| key(c) == key(region)) {
+                out.push(region.clone());
+            }
+        }
+        out
+    }
+}
+
+/// Options for [Editable::import_animations]
+#[derive(Clone, Debug, Default)]
+pub struct ImportOptions {
+    /// Whether to replace animations that already exist with the same name, instead of failing.
+    /// Replaced animations keep their position in the animation list
+    pub replace: bool,
+    /// Offset added to the texture regions of imported sprites, for when the source texture was pasted
+    /// somewhere else in this BXCAD's texture
+    pub region_offset: (u16, u16),
+}
+
+impl Editable for bccad::BCCAD {
+    type Sprite = bccad::Sprite;
+    type Animation = bccad::Animation;
+
+    fn sprites(&self) -> &Vec<Self::Sprite> {
+        &self.sprites
+    }
+
+    fn sprites_mut(&mut self) -> &mut Vec<Self::Sprite> {
+        &mut self.sprites
+    }
+
+    fn animations(&self) -> &Vec<Self::Animation> {
+        &self.animations
+    }
+
+    fn animations_mut(&mut self) -> &mut Vec<Self::Animation> {
+        &mut self.animations
+    }
+
+    fn animation_name(anim: &Self::Animation) -> Option<&str> {
+        Some(&anim.name)
+    }
+
+    fn animation_sprites(anim: &Self::Animation) -> Vec<u16> {
+        anim.steps.iter().map(|step| step.sprite).collect()
+    }
+
+    fn animation_sprites_mut(anim: &mut Self::Animation) -> Vec<&mut u16> {
+        anim.steps.iter_mut().map(|step| &mut step.sprite).collect()
+    }
+
+    fn sprite_regions(sprite: &Self::Sprite) -> Vec<&PosInTexture> {
+        sprite.parts.iter().map(|c| &c.texture_pos).collect()
+    }
+
+    fn sprite_regions_mut(sprite: &mut Self::Sprite) -> Vec<&mut PosInTexture> {
+        sprite
+            .parts
+            .iter_mut()
+            .map(|c| &mut c.texture_pos)
+            .collect()
+    }
+}
+
+impl Editable for brcad::BRCAD {
+    type Sprite = brcad::Sprite;
+    type Animation = brcad::Animation;
+
+    fn sprites(&self) -> &Vec<Self::Sprite> {
+        &self.sprites
+    }
+
+    fn sprites_mut(&mut self) -> &mut Vec<Self::Sprite> {
+        &mut self.sprites
+    }
+
+    fn animations(&self) -> &Vec<Self::Animation> {
+        &self.animations
+    }
+
+    fn animations_mut(&mut self) -> &mut Vec<Self::Animation> {
+        &mut self.animations
+    }
+
+    fn animation_name(anim: &Self::Animation) -> Option<&str> {
+        anim.name.as_deref()
+    }
+
+    fn animation_sprites(anim: &Self::Animation) -> Vec<u16> {
+        anim.steps.iter().map(|step| step.sprite).collect()
+    }
+
+    fn animation_sprites_mut(anim: &mut Self::Animation) -> Vec<&mut u16> {
+        anim.steps.iter_mut().map(|step| &mut step.sprite).collect()
+    }
+
+    fn sprite_regions(sprite: &Self::Sprite) -> Vec<&PosInTexture> {
+        sprite.parts.iter().map(|c| &c.texture_pos).collect()
+    }
+
+    fn sprite_regions_mut(sprite: &mut Self::Sprite) -> Vec<&mut PosInTexture> {
+        sprite
+            .parts
+            .iter_mut()
+            .map(|c| &mut c.texture_pos)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BCCAD;
+    use std::io::Cursor;
+
+    fn sample() -> BCCAD {
+        BCCAD::from_binary(&mut Cursor::new(include_bytes!(
+            "../../tests/fixtures/sample.bccad"
+        )))
+        .unwrap()
+    }
+
+    #[test]
+    fn insert_sprite_shifts_references() {
+        let mut bccad = sample();
+        let sprite = bccad.sprites[0].clone();
+        bccad.insert_sprite(1, sprite).unwrap();
+        assert_eq!(bccad.step_sprites(), vec![vec![0, 2], vec![3]]);
+    }
+
+    #[test]
+    fn insert_sprite_doesnt_overflow_references() {
+        let mut bccad = sample();
+        bccad.animations[1].steps[0].sprite = u16::MAX;
+        let sprite = bccad.sprites[0].clone();
+        let result = bccad.insert_sprite(0, sprite);
+        assert!(matches!(result, Err(Error::TooManySprites)));
+        assert_eq!(bccad.sprites.len(), 4);
+        assert_eq!(bccad.step_sprites(), vec![vec![0, 1], vec![u16::MAX]]);
+    }
+
+    #[test]
+    fn duplicate_sprites_with_different_names_are_merged() {
+        let mut bccad = sample();
+        bccad.sprites[0].name = Some("first".to_string());
+        bccad.sprites[2].name = Some("second".to_string());
+        assert_eq!(bccad.merge_duplicate_sprites(), vec![(2, 0)]);
+        assert_eq!(bccad.sprites.len(), 3);
+        assert_eq!(bccad.step_sprites(), vec![vec![0, 1], vec![0]]);
+    }
+
+    #[test]
+    fn failed_import_changes_nothing() {
+        let mut bccad = sample();
+        let mut other = sample();
+        other.animations[1].name = "new".to_string();
+        // "new" could be imported on its own, but "beat" already exists
+        let result = bccad.import_animations(&other, &["new", "beat"], &ImportOptions::default());
+        assert!(matches!(result, Err(Error::AnimationExists(_))));
+        assert_eq!(bccad.sprites.len(), 4);
+        let names: Vec<_> = bccad.animations.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["beat", "idle"]);
+    }
+}
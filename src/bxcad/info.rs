@@ -0,0 +1,222 @@
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::bxcad::{bccad::BCCAD, brcad::BRCAD, edit::Editable, BXCADType};
+
+/// A summary of the contents of a BXCAD
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Info {
+    /// Type of the BXCAD
+    pub bxcad_type: BXCADType,
+    /// Format revision timestamp of the BXCAD
+    pub timestamp: u32,
+    /// Whether the timestamp is the one that's known for the BXCAD type (see [crate::BXCAD::TIMESTAMP])
+    pub known_timestamp: bool,
+    /// Width of the associated texture in pixels
+    pub texture_width: u16,
+    /// Height of the associated texture in pixels
+    pub texture_height: u16,
+    /// (BRCAD only) Index of the associated texture sheet
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spritesheet_num: Option<u16>,
+    /// (BRCAD only) Whether the associated texture sheet has variations
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub has_variations: Option<bool>,
+    /// Number of sprites
+    pub sprites: usize,
+    /// Number of sprite parts, across all sprites
+    pub parts: usize,
+    /// Summary of each animation
+    pub animations: Vec<AnimationInfo>,
+    /// Number of animation steps, across all animations
+    pub steps: usize,
+    /// Indexes of the sprites that no animation step uses
+    pub unreferenced_sprites: Vec<u16>,
+}
+
+/// A summary of an animation, as found in [Info]
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct AnimationInfo {
+    /// Name of the animation, if it has one
+    pub name: Option<String>,
+    /// Number of steps
+    pub steps: usize,
+    /// Sum of the durations of every step
+    pub duration: u32,
+}
+
+impl Info {
+    fn new<X: Editable>(
+        bxcad: &X,
+        timestamp: Option<u32>,
+        texture_size: (u16, u16),
+        durations: impl Fn(&X::Animation) -> Vec<u16>,
+    ) -> Self {
+        let animations: Vec<_> = bxcad
+            .animations()
+            .iter()
+            .map(|c| {
+                let durations = durations(c);
+                AnimationInfo {
+                    name: X::animation_name(c).map(str::to_string),
+                    steps: durations.len(),
+                    duration: durations.iter().map(|&c| c as u32).sum(),
+                }
+            })
+            .collect();
+
+        let mut used = vec![false; bxcad.sprites().len()];
+        for sprite in bxcad.step_sprites().into_iter().flatten() {
+            if let Some(c) = used.get_mut(sprite as usize) {
+                *c = true;
+            }
+        }
+
+        Self {
+            bxcad_type: X::BXCAD_TYPE,
+            timestamp: timestamp.unwrap_or(X::TIMESTAMP),
+            known_timestamp: timestamp.is_none_or(|c| c == X::TIMESTAMP),
+            texture_width: texture_size.0,
+            texture_height: texture_size.1,
+            spritesheet_num: None,
+            has_variations: None,
+            sprites: bxcad.sprites().len(),
+            parts: bxcad
+                .sprites()
+                .iter()
+                .map(|c| X::sprite_regions(c).len())
+                .sum(),
+            steps: animations.iter().map(|c| c.steps).sum(),
+            animations,
+            unreferenced_sprites: (0..used.len() as u16)
+                .filter(|&c| !used[c as usize])
+                .collect(),
+        }
+    }
+}
+
+impl From<&BCCAD> for Info {
+    fn from(bccad: &BCCAD) -> Self {
+        Self::new(
+            bccad,
+            bccad.timestamp,
+            (bccad.texture_width, bccad.texture_height),
+            |c| c.steps.iter().map(|c| c.duration).collect(),
+        )
+    }
+}
+
+impl From<&BRCAD> for Info {
+    fn from(brcad: &BRCAD) -> Self {
+        Self {
+            spritesheet_num: Some(brcad.spritesheet_num),
+            has_variations: Some(brcad.has_variations()),
+            ..Self::new(
+                brcad,
+                brcad.timestamp,
+                (brcad.texture_width, brcad.texture_height),
+                |c| c.steps.iter().map(|c| c.duration).collect(),
+            )
+        }
+    }
+}
+
+impl fmt::Display for Info {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Type: {:?}", self.bxcad_type)?;
+        write!(f, "Timestamp: {}", self.timestamp)?;
+        if !self.known_timestamp {
+            write!(f, " (unknown, might be a different version of the format)")?;
+        }
+        writeln!(f)?;
+        writeln!(
+            f,
+            "Texture size: {}x{}",
+            self.texture_width, self.texture_height
+        )?;
+        if let Some(c) = self.spritesheet_num {
+            writeln!(f, "Texture sheet: {}", c)?;
+        }
+        if let Some(c) = self.has_variations {
+            writeln!(f, "Has variations: {}", c)?;
+        }
+        writeln!(
+            f,
+            "Sprites: {} ({})",
+            self.sprites,
+            plural(self.parts, "part")
+        )?;
+        writeln!(
+            f,
+            "Animations: {} ({})",
+            self.animations.len(),
+            plural(self.steps, "step")
+        )?;
+        for (i, anim) in self.animations.iter().enumerate() {
+            match &anim.name {
+                Some(c) => write!(f, "    {}", c)?,
+                None => write!(f, "    #{}", i)?,
+            }
+            writeln!(
+                f,
+                ": {}, duration {}",
+                plural(anim.steps, "step"),
+                anim.duration
+            )?;
+        }
+        if self.unreferenced_sprites.is_empty() {
+            writeln!(f, "Unreferenced sprites: none")?;
+        } else {
+            let sprites: Vec<_> = self
+                .unreferenced_sprites
+                .iter()
+                .map(u16::to_string)
+                .collect();
+            writeln!(f, "Unreferenced sprites: {}", sprites.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+fn plural(count: usize, noun: &str) -> String {
+    match count {
+        1 => format!("1 {}", noun),
+        c => format!("{} {}s", c, noun),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BXCAD;
+    use std::io::Cursor;
+
+    /// The accessor, the JSON and the summary all have to tell the same thing
+    fn assert_has_variations(brcad: &BRCAD, expected: bool) {
+        assert_eq!(brcad.has_variations(), expected);
+        let json = serde_json::to_value(brcad).unwrap();
+        assert_eq!(json["has_variations"], expected);
+        assert_eq!(Info::from(brcad).has_variations, Some(expected));
+    }
+
+    #[test]
+    fn has_variations_agrees_everywhere() {
+        let mut brcad = BRCAD::from_binary(&mut Cursor::new(include_bytes!(
+            "../../tests/fixtures/sample.brcad"
+        )))
+        .unwrap();
+        assert_has_variations(&brcad, false);
+
+        *brcad.has_variations_mut() = true;
+        assert_has_variations(&brcad, true);
+
+        let mut bytes = vec![];
+        brcad.to_binary(&mut bytes).unwrap();
+        // the flag is the first byte of the field after the timestamp
+        assert_eq!(bytes[4..8], [1, 0, 0, 0]);
+        let json = serde_json::to_string(&brcad).unwrap();
+        let brcad: BRCAD = serde_json::from_str(&json).unwrap();
+        assert_has_variations(&brcad, true);
+    }
+}
@@ -0,0 +1,331 @@
+use std::fmt;
+
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{Map, Value};
+
+use crate::{bxcad::diff::named_elements, error::Result, BXCAD};
+
+/// The result of a three-way merge between BXCADs, see [merge]
+pub struct Merge<X> {
+    /// The merged BXCAD. Conflicting values are taken from "our" version
+    pub merged: X,
+    /// Every value that was changed differently in both versions
+    pub conflicts: Vec<Conflict>,
+}
+
+/// A value that was changed differently in both versions being merged
+///
+/// Each value is `None` if it doesn't exist in that version, and is given as it'd appear in flour JSON
+#[derive(Clone, Debug, PartialEq)]
+pub struct Conflict {
+    /// Location of the value, like `animations["beat"].steps[2].duration`
+    pub path: String,
+    /// The value in the common ancestor of both versions
+    pub base: Option<Value>,
+    /// The value in "our" version
+    pub ours: Option<Value>,
+    /// The value in "their" version
+    pub theirs: Option<Value>,
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let show = |c: &Option<Value>| match c {
+            Some(c) => c.to_string(),
+            None => "(missing)".to_string(),
+        };
+        write!(
+            f,
+            "{}: ours {}, theirs {} (base {})",
+            self.path,
+            show(&self.ours),
+            show(&self.theirs),
+            show(&self.base)
+        )
+    }
+}
+
+/// Merges the changes made by two versions of a BXCAD ("ours" and "theirs") to their common ancestor
+/// ("base")
+///
+/// Changes are merged field by field, so edits to different sprites, parts, animations or steps, or even
+/// to different fields of the same one, don't conflict. Like in [diff](crate::bxcad::diff::diff), sprites,
+/// parts and steps are matched by their position in their list, and animations are matched by their name.
+pub fn merge<X: BXCAD + Serialize + DeserializeOwned>(
+    base: &X,
+    ours: &X,
+    theirs: &X,
+) -> Result<Merge<X>> {
+    let base = serde_json::to_value(base)?;
+    let ours = serde_json::to_value(ours)?;
+    let theirs = serde_json::to_value(theirs)?;
+    let mut conflicts = vec![];
+    let merged = merge_value(
+        &mut conflicts,
+        String::new(),
+        Some(&base),
+        Some(&ours),
+        Some(&theirs),
+    )
+    .unwrap_or(Value::Null);
+    Ok(Merge {
+        merged: serde_json::from_value(merged)?,
+        conflicts,
+    })
+}
+
+fn merge_value(
+    conflicts: &mut Vec<Conflict>,
+    path: String,
+    base: Option<&Value>,
+    ours: Option<&Value>,
+    theirs: Option<&Value>,
+) -> Option<Value> {
+    if ours == theirs || base == theirs {
+        return ours.cloned();
+    }
+    if base == ours {
+        return theirs.cloned();
+    }
+    match (base, ours, theirs) {
+        (Some(Value::Object(base)), Some(Value::Object(ours)), Some(Value::Object(theirs))) => {
+            Some(Value::Object(merge_object(
+                conflicts, &path, base, ours, theirs,
+            )))
+        }
+        (Some(Value::Array(base)), Some(Value::Array(ours)), Some(Value::Array(theirs))) => {
+            let named = (
+                named_elements(&path, base),
+                named_elements(&path, ours),
+                named_elements(&path, theirs),
+            );
+            Some(Value::Array(match named {
+                (Some(base), Some(ours), Some(theirs)) => {
+                    merge_named(conflicts, &path, &base, &ours, &theirs)
+                }
+                _ => merge_array(conflicts, &path, base, ours, theirs),
+            }))
+        }
+        _ => {
+            conflicts.push(Conflict {
+                path,
+                base: base.cloned(),
+                ours: ours.cloned(),
+                theirs: theirs.cloned(),
+            });
+            ours.cloned()
+        }
+    }
+}
+
+fn merge_object(
+    conflicts: &mut Vec<Conflict>,
+    path: &str,
+    base: &Map<String, Value>,
+    ours: &Map<String, Value>,
+    theirs: &Map<String, Value>,
+) -> Map<String, Value> {
+    let mut out = Map::new();
+    let keys = ours
+        .keys()
+        .chain(theirs.keys().filter(|c| !ours.contains_key(*c)));
+    for key in keys {
+        let field_path = if path.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", path, key)
+        };
+        if let Some(c) = merge_value(
+            conflicts,
+            field_path,
+            base.get(key),
+            ours.get(key),
+            theirs.get(key),
+        ) {
+            out.insert(key.clone(), c);
+        }
+    }
+    out
+}
+
+fn merge_array(
+    conflicts: &mut Vec<Conflict>,
+    path: &str,
+    base: &[Value],
+    ours: &[Value],
+    theirs: &[Value],
+) -> Vec<Value> {
+    let len = base.len().max(ours.len()).max(theirs.len());
+    (0..len)
+        .filter_map(|i| {
+            merge_value(
+                conflicts,
+                format!("{}[{}]", path, i),
+                base.get(i),
+                ours.get(i),
+                theirs.get(i),
+            )
+        })
+        .collect()
+}
+
+fn merge_named(
+    conflicts: &mut Vec<Conflict>,
+    path: &str,
+    base: &[(&str, &Value)],
+    ours: &[(&str, &Value)],
+    theirs: &[(&str, &Value)],
+) -> Vec<Value> {
+    let names = ours.iter().chain(
+        theirs
+            .iter()
+            .filter(|(name, _)| find_named(ours, name).is_none()),
+    );
+    names
+        .filter_map(|(name, _)| {
+            merge_value(
+                conflicts,
+                format!("{}[{}]", path, Value::from(*name)),
+                find_named(base, name),
+                find_named(ours, name),
+                find_named(theirs, name),
+            )
+        })
+        .collect()
+}
+
+fn find_named<'a>(list: &[(&str, &'a Value)], name: &str) -> Option<&'a Value> {
+    list.iter().find(|(c, _)| *c == name).map(|(_, c)| *c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BCCAD;
+    use std::io::Cursor;
+
+    fn sample() -> BCCAD {
+        BCCAD::from_binary(&mut Cursor::new(include_bytes!(
+            "../../tests/fixtures/sample.bccad"
+        )))
+        .unwrap()
+    }
+
+    fn json<T: Serialize>(value: &T) -> Value {
+        serde_json::to_value(value).unwrap()
+    }
+
+    #[test]
+    fn clean_merge() {
+        let base = sample();
+        let mut ours = sample();
+        ours.texture_width = 1024;
+        ours.animations[0].steps[0].duration = 5;
+        let mut theirs = sample();
+        theirs.animations[0].steps[1].duration = 3;
+        theirs.sprites[0].parts[0].pos_x += 10;
+
+        let result = merge(&base, &ours, &theirs).unwrap();
+        assert_eq!(result.conflicts, []);
+        let mut expected = sample();
+        expected.texture_width = 1024;
+        expected.animations[0].steps[0].duration = 5;
+        expected.animations[0].steps[1].duration = 3;
+        expected.sprites[0].parts[0].pos_x += 10;
+        assert_eq!(json(&result.merged), json(&expected));
+    }
+
+    #[test]
+    fn field_conflict() {
+        let base = sample();
+        let mut ours = sample();
+        ours.animations[0].steps[0].duration = 5;
+        let mut theirs = sample();
+        theirs.animations[0].steps[0].duration = 6;
+
+        let result = merge(&base, &ours, &theirs).unwrap();
+        assert_eq!(
+            result.conflicts,
+            [Conflict {
+                path: "animations[\"beat\"].steps[0].duration".to_string(),
+                base: Some(4.into()),
+                ours: Some(5.into()),
+                theirs: Some(6.into()),
+            }]
+        );
+        assert_eq!(
+            result.conflicts[0].to_string(),
+            "animations[\"beat\"].steps[0].duration: ours 5, theirs 6 (base 4)"
+        );
+        assert_eq!(json(&result.merged), json(&ours));
+    }
+
+    #[test]
+    fn deletion_on_one_side() {
+        let base = sample();
+        let mut ours = sample();
+        ours.texture_width = 1024;
+        let mut theirs = sample();
+        theirs.sprites.pop();
+        theirs.animations.retain(|c| c.name != "idle");
+
+        let result = merge(&base, &ours, &theirs).unwrap();
+        assert_eq!(result.conflicts, []);
+        assert_eq!(result.merged.texture_width, 1024);
+        assert_eq!(json(&result.merged.sprites), json(&theirs.sprites));
+        assert_eq!(json(&result.merged.animations), json(&theirs.animations));
+
+        // Deleting something the other side changed is a conflict
+        ours.animations[1].steps[0].duration = 9;
+        let result = merge(&base, &ours, &theirs).unwrap();
+        assert_eq!(result.conflicts.len(), 1);
+        assert_eq!(result.conflicts[0].path, "animations[\"idle\"]");
+        assert!(result.conflicts[0].ours.is_some());
+        assert_eq!(result.conflicts[0].theirs, None);
+    }
+
+    #[test]
+    fn both_sides_appending_conflicts() {
+        let base = sample();
+        let mut ours = sample();
+        let mut ours_sprite = ours.sprites[1].clone();
+        ours_sprite.parts[0].pos_x += 1;
+        ours.sprites.push(ours_sprite.clone());
+        let mut theirs = sample();
+        let mut their_sprite = theirs.sprites[1].clone();
+        their_sprite.parts[0].pos_x += 2;
+        theirs.sprites.push(their_sprite.clone());
+
+        let result = merge(&base, &ours, &theirs).unwrap();
+        assert_eq!(
+            result.conflicts,
+            [Conflict {
+                path: "sprites[4]".to_string(),
+                base: None,
+                ours: Some(json(&ours_sprite)),
+                theirs: Some(json(&their_sprite)),
+            }]
+        );
+    }
+
+    #[test]
+    fn animations_are_matched_by_name() {
+        let base = sample();
+        let mut ours = sample();
+        ours.animations.reverse();
+        ours.animations[1].steps[0].duration = 5;
+        let mut theirs = sample();
+        theirs.animations[1].steps[0].duration = 7;
+        let mut walk = theirs.animations[0].clone();
+        walk.name = "walk".to_string();
+        theirs.animations.push(walk.clone());
+
+        let result = merge(&base, &ours, &theirs).unwrap();
+        assert_eq!(result.conflicts, []);
+        let names: Vec<_> = result.merged.animations.iter().map(|c| &c.name).collect();
+        assert_eq!(names, ["idle", "beat", "walk"]);
+        assert_eq!(result.merged.animations[0].steps[0].duration, 7);
+        assert_eq!(result.merged.animations[1].steps[0].duration, 5);
+        assert_eq!(json(&result.merged.animations[2]), json(&walk));
+    }
+}
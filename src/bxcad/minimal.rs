@@ -0,0 +1,83 @@
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Map, Value};
+
+use crate::{
+    bxcad::{bccad, brcad},
+    error::{Error, Result},
+};
+
+/// Removes the fields of sprite parts and animation steps that have their default value from
+/// the JSON for a [BXCADWrapper](crate::bxcad::BXCADWrapper)
+///
+/// The result deserializes to the same BXCAD, since those fields get filled back in with
+/// their defaults.
+pub fn omit_defaults(wrapper: &mut Value) -> Result<()> {
+    let texture_pos = json!({"x": 0, "y": 0, "width": 0, "height": 0});
+    let (part_defaults, step_defaults) = match wrapper.get("bxcad_type").and_then(Value::as_str) {
+        Some("BCCAD") => (
+            defaults::<bccad::SpritePart>(json!({ "texture_pos": texture_pos }))?,
+            defaults::<bccad::AnimationStep>(json!({"sprite": 0, "duration": 0}))?,
+        ),
+        Some("BRCAD") => (
+            defaults::<brcad::SpritePart>(json!({ "texture_pos": texture_pos }))?,
+            defaults::<brcad::AnimationStep>(json!({"sprite": 0, "duration": 0}))?,
+        ),
+        _ => Err(Error::NotFlour)?,
+    };
+    let Some(data) = wrapper.get_mut("data") else {
+        Err(Error::NotFlour)?
+    };
+
+    for sprite in elements(data.get_mut("sprites")) {
+        for part in elements(sprite.get_mut("parts")) {
+            omit(part, &part_defaults);
+        }
+    }
+    for anim in elements(data.get_mut("animations")) {
+        for step in elements(anim.get_mut("steps")) {
+            omit(step, &step_defaults);
+        }
+    }
+    Ok(())
+}
+
+/// Default values of a struct's fields, figured out by deserializing it with only the
+/// required fields given
+fn defaults<T: DeserializeOwned + Serialize>(required: Value) -> Result<Map<String, Value>> {
+    let Value::Object(mut defaults) = serde_json::to_value(T::deserialize(&required)?)? else {
+        unreachable!()
+    };
+    defaults.retain(|key, _| required.get(key).is_none());
+    Ok(defaults)
+}
+
+fn omit(object: &mut Value, defaults: &Map<String, Value>) {
+    if let Some(object) = object.as_object_mut() {
+        object.retain(|key, value| defaults.get(key).is_none_or(|c| !same(c, value)));
+    }
+}
+
+/// Like `==`, but doesn't take -0.0 for 0.0, since that would change the BXCAD
+fn same(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => {
+            a == b && a.as_f64().map(f64::is_sign_negative) == b.as_f64().map(f64::is_sign_negative)
+        }
+        (Value::Array(a), Value::Array(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| same(a, b))
+        }
+        (Value::Object(a), Value::Object(b)) => {
+            a.len() == b.len() && a.iter().all(|(k, v)| b.get(k).is_some_and(|c| same(v, c)))
+        }
+        _ => a == b,
+    }
+}
+
+/// Elements of a JSON array, or values of a JSON object (like indexized sprites)
+fn elements(value: Option<&mut Value>) -> Vec<&mut Value> {
+    match value {
+        Some(Value::Array(c)) => c.iter_mut().collect(),
+        Some(Value::Object(c)) => c.values_mut().collect(),
+        _ => vec![],
+    }
+}
@@ -1,6 +1,5 @@
-use crate::error::{Error, Result};
+use crate::error::Result;
 use bytestream::{ByteOrder, StreamReader};
-use semver::{Version, VersionReq};
 use serde::{Deserialize, Serialize};
 use std::io::{Read, Seek, SeekFrom, Write};
 
@@ -11,6 +10,25 @@ pub mod bccad;
 /// Everything related to the BRCAD format used in Rhythm Heaven Fever
 pub mod brcad;
 
+/// Structural comparison between BXCADs
+pub mod diff;
+/// Annotated dumps of binary BXCAD files
+pub mod dump;
+/// Editing operations that keep sprite references valid
+pub mod edit;
+/// Summaries of the contents of BXCADs
+pub mod info;
+/// Three-way merging of BXCADs
+pub mod merge;
+/// Minimal JSON, without the fields that have their default values
+pub mod minimal;
+/// Splitting flour JSON into a directory with a file per sprite and animation
+pub mod split;
+/// Statistics about the values of fields across many BXCADs
+pub mod stats;
+/// Upgrading flour JSON made with older flour versions
+pub mod upgrade;
+
 /// Custom implementations for (de)serialization
 mod serde_impl;
 
@@ -48,6 +66,7 @@ pub trait BXCAD: Sized {
 
 /// An enum of all the different types of BXCAD supported by this library
 #[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[non_exhaustive]
 pub enum BXCADType {
     /// BRCAD, used with Rhythm Heaven Fever. See [`brcad`]
@@ -75,7 +94,8 @@ pub fn get_bxcad_type<F: Read + Seek>(f: &mut F) -> Result<Option<BXCADType>> {
 }
 
 /// Bounding box for a sprite part's texture in the texture sheet
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct PosInTexture {
     pub x: u16,
     pub y: u16,
@@ -86,6 +106,11 @@ pub struct PosInTexture {
 /// A wrapper that contains data about the BXCAD file, meant to be used
 /// with serializing/deserializing (see the flour main executable)
 #[derive(Serialize, Deserialize)]
+#[cfg_attr(
+    feature = "schema",
+    derive(schemars::JsonSchema),
+    schemars(rename = "BXCADWrapper_for_{X}")
+)]
 #[non_exhaustive]
 pub struct BXCADWrapper<X> {
     /// Type of the BXCAD file
@@ -95,6 +120,14 @@ pub struct BXCADWrapper<X> {
     /// QoL options existing in the JSON file
     #[serde(default)]
     pub indexize: bool,
+    /// Whether the fields that have their default value were left out of the JSON file (see
+    /// [minimal::omit_defaults]), so that the file can be written back the same way
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub omit_defaults: bool,
+    /// Whether colors were written as `"#rrggbb"` strings (see [crate::json::colors_to_hex]),
+    /// so that the file can be written back the same way
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub hex_colors: bool,
     /// Actual BXCAD data
     data: X,
 }
@@ -108,23 +141,17 @@ impl<X: BXCAD> BXCADWrapper<X> {
             bxcad_type: X::BXCAD_TYPE,
             flour_version: env!("CARGO_PKG_VERSION").to_string(),
             indexize: false,
+            omit_defaults: false,
+            hex_colors: false,
             data: bxcad,
         }
     }
 
     /// Return the wrapper's BXCAD data if compatible
+    ///
+    /// See [upgrade::check_version] for which files are compatible
     pub fn to_bxcad(self) -> Result<X> {
-        let requirement = VersionReq::parse(&format!(
-            "<={}, >={}",
-            env!("CARGO_PKG_VERSION"),
-            OLDEST_SUPPORTED_VERSION
-        ))?;
-        let version = Version::parse(&self.flour_version)?; //TODO: add specific error
-
-        if !requirement.matches(&version) {
-            //TODO: add specific error
-            return Err(Error::IncompatibleVersion(version));
-        }
+        upgrade::check_version(&self.flour_version)?;
 
         //TODO: this might false-positive some bxcads
         Ok(self.data)
@@ -134,21 +161,13 @@ impl<X: BXCAD> BXCADWrapper<X> {
 #[cfg(feature = "modder_qol")]
 impl<I> BXCADWrapper<I> {
     /// Return the wrapper's BXCAD data, deindexized, if compatible
+    ///
+    /// See [upgrade::check_version] for which files are compatible
     pub fn indexized_to_bxcad<X: Indexizable<Indexized = I>>(self) -> Result<X> {
-        let requirement = VersionReq::parse(&format!(
-            "<={}, >={}",
-            env!("CARGO_PKG_VERSION"),
-            OLDEST_SUPPORTED_VERSION
-        ))?;
-        let version = Version::parse(&self.flour_version)?; //TODO: add specific error
-
-        if !requirement.matches(&version) {
-            //TODO: add specific error
-            return Err(Error::IncompatibleVersion(version));
-        }
+        upgrade::check_version(&self.flour_version)?;
 
         //TODO: this might false-positive some bxcads
-        Ok(X::from_indexized(self.data))
+        X::from_indexized(self.data)
     }
 }
 
@@ -161,6 +180,21 @@ impl<X: qol::Indexizable> BXCADWrapper<X> {
             bxcad_type: X::BXCAD_TYPE,
             flour_version: env!("CARGO_PKG_VERSION").to_string(),
             indexize: true,
+            omit_defaults: false,
+            hex_colors: false,
+            data,
+        }
+    }
+
+    /// Create a [BXCADWrapper] for the BXCAD that applies [Indexizable::to_indexized_named]
+    pub fn from_bxcad_indexize_named(bxcad: X) -> BXCADWrapper<X::Indexized> {
+        let data = bxcad.to_indexized_named();
+        BXCADWrapper {
+            bxcad_type: X::BXCAD_TYPE,
+            flour_version: env!("CARGO_PKG_VERSION").to_string(),
+            indexize: true,
+            omit_defaults: false,
+            hex_colors: false,
             data,
         }
     }
@@ -1,10 +1,19 @@
-use serde::{Deserialize, Serialize};
+use serde::{
+    de::{self, DeserializeOwned, MapAccess, SeqAccess, Unexpected, Visitor},
+    ser::SerializeMap,
+    Deserialize, Deserializer, Serialize, Serializer,
+};
 
 use crate::{
     bxcad::{bccad, brcad},
+    error::Error,
     BXCAD,
 };
-use std::collections::BTreeMap;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt,
+    marker::PhantomData,
+};
 
 /// Trait for BXCAD types that allow "indexization", that is, conversion for their `Sprite` lists
 /// from a Vec to some sort of Map (usually `BTreeMap<uX, Sprite>`)
@@ -12,6 +21,12 @@ use std::collections::BTreeMap;
 /// This is so that the list of `Sprite`s can be more easily edited by hand, and more easily referenced,
 /// due to including the number of the sprite right next to the entry.
 ///
+/// Animations can also be indexized by name (see [IndexizedAnimations]), which turns the list of animations
+/// into an ordered map keyed by each animation's name.
+///
+/// In indexized BXCADs, animation steps can refer to a sprite by its name instead of its index (see
+/// [SpriteRef]). These names are resolved back to indexes by [Indexizable::from_indexized].
+///
 /// Unless you're going to deal with `BXCAD` files by hand (for example, as JSON in the flour binary), you
 /// probably don't need this.
 pub trait Indexizable: BXCAD {
@@ -19,25 +34,113 @@ pub trait Indexizable: BXCAD {
     type Indexized;
     /// Convert a standard BXCAD to indexized
     fn to_indexized(self) -> Self::Indexized;
+    /// Convert a standard BXCAD to indexized, with its animations indexized by name if no two of
+    /// them share one
+    ///
+    /// By default, animations are left as a list, same as [Indexizable::to_indexized].
+    fn to_indexized_named(self) -> Self::Indexized {
+        self.to_indexized()
+    }
     /// Convert the indexized data to a standard BXCAD
-    fn from_indexized(og: Self::Indexized) -> Self;
+    ///
+    /// Fails if an animation step refers to a sprite name that doesn't exist, or that is shared
+    /// by several sprites
+    fn from_indexized(og: Self::Indexized) -> Result<Self, Error>;
+}
+
+/// A reference to a sprite in an animation step of an indexized BXCAD
+///
+/// (De)serialized as an integer for [SpriteRef::Index] and as a string for [SpriteRef::Name]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SpriteRef {
+    /// Index number of the sprite
+    Index(u16),
+    /// Name of the sprite, as given by its `name` field
+    Name(String),
+}
+
+/// Lookup table between sprite indexes and sprite names
+struct SpriteNames<'a> {
+    /// Index of the sprite with each name, or `None` if the name is used by more than one sprite
+    indexes: HashMap<&'a str, Option<u16>>,
+}
+
+impl<'a> SpriteNames<'a> {
+    fn new(names: impl Iterator<Item = (u16, Option<&'a str>)>) -> Self {
+        let mut indexes = HashMap::new();
+        for (i, name) in names {
+            if let Some(name) = name {
+                indexes
+                    .entry(name)
+                    .and_modify(|c| *c = None)
+                    .or_insert(Some(i));
+            }
+        }
+        Self { indexes }
+    }
+
+    /// Reference for the given sprite: its name if it's unique, its index otherwise
+    fn to_ref(&self, index: u16, name: Option<&str>) -> SpriteRef {
+        match name {
+            Some(name) if self.indexes.get(name) == Some(&Some(index)) => {
+                SpriteRef::Name(name.to_string())
+            }
+            _ => SpriteRef::Index(index),
+        }
+    }
+
+    fn resolve(&self, sprite: SpriteRef) -> Result<u16, Error> {
+        match sprite {
+            SpriteRef::Index(c) => Ok(c),
+            SpriteRef::Name(name) => match self.indexes.get(name.as_str()) {
+                Some(Some(c)) => Ok(*c),
+                Some(None) => Err(Error::AmbiguousSpriteName(name)),
+                None => Err(Error::UnknownSpriteName(name)),
+            },
+        }
+    }
 }
 
 /// [Indexizable::Indexized] variant of [crate::BCCAD]
 ///
 /// See that type for more information
 #[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct IndexizedBCCAD {
     pub timestamp: Option<u32>,
     pub texture_width: u16,
     pub texture_height: u16,
     pub sprites: BTreeMap<u16, bccad::Sprite>,
-    pub animations: Vec<bccad::Animation>,
+    pub animations: IndexizedAnimations<bccad::Animation<SpriteRef>>,
 }
 
 impl Indexizable for bccad::BCCAD {
     type Indexized = IndexizedBCCAD;
     fn to_indexized(self) -> Self::Indexized {
+        let names = SpriteNames::new(
+            (0..)
+                .zip(&self.sprites)
+                .map(|(i, c)| (i, c.name.as_deref())),
+        );
+        let animations = self
+            .animations
+            .into_iter()
+            .map(|anim| bccad::Animation {
+                name: anim.name,
+                interpolation: anim.interpolation,
+                steps: anim
+                    .steps
+                    .into_iter()
+                    .map(|step| {
+                        let sprite = self.sprites.get(step.sprite as usize);
+                        let sprite =
+                            names.to_ref(step.sprite, sprite.and_then(|c| c.name.as_deref()));
+                        step.with_sprite(sprite)
+                    })
+                    .collect(),
+            })
+            .collect();
+
         let mut sprites = BTreeMap::new();
 
         for (i, sprite) in self.sprites.iter().enumerate() {
@@ -49,28 +152,54 @@ impl Indexizable for bccad::BCCAD {
             texture_width: self.texture_width,
             texture_height: self.texture_height,
             sprites,
-            animations: self.animations,
+            animations: IndexizedAnimations::List(animations),
         }
     }
 
-    fn from_indexized(og: Self::Indexized) -> Self {
+    fn to_indexized_named(self) -> Self::Indexized {
+        let mut indexized = self.to_indexized();
+        indexized.animations = IndexizedAnimations::named(indexized.animations.into_vec());
+        indexized
+    }
+
+    fn from_indexized(og: Self::Indexized) -> Result<Self, Error> {
+        let names = SpriteNames::new(og.sprites.iter().map(|(i, c)| (*i, c.name.as_deref())));
+        let mut animations = vec![];
+        for anim in og.animations.into_vec() {
+            let mut steps = vec![];
+            for step in anim.steps {
+                let sprite = names.resolve(step.sprite.clone())?;
+                steps.push(step.with_sprite(sprite));
+            }
+            animations.push(bccad::Animation {
+                name: anim.name,
+                interpolation: anim.interpolation,
+                steps,
+            });
+        }
+
         let mut sprites = vec![];
 
-        for i in 0..=*og.sprites.keys().max().unwrap() {
-            let sprite = match og.sprites.get(&i) {
-                Some(c) => c.clone(),
-                None => bccad::Sprite { parts: vec![] },
-            };
-            sprites.push(sprite);
+        if let Some(max) = og.sprites.keys().max() {
+            for i in 0..=*max {
+                let sprite = match og.sprites.get(&i) {
+                    Some(c) => c.clone(),
+                    None => bccad::Sprite {
+                        name: None,
+                        parts: vec![],
+                    },
+                };
+                sprites.push(sprite);
+            }
         }
 
-        Self {
+        Ok(Self {
             timestamp: og.timestamp,
             texture_width: og.texture_width,
             texture_height: og.texture_height,
             sprites,
-            animations: og.animations,
-        }
+            animations,
+        })
     }
 }
 
@@ -78,6 +207,7 @@ impl Indexizable for bccad::BCCAD {
 ///
 /// See that type for more information
 #[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct IndexizedBRCAD {
     pub timestamp: Option<u32>,
     pub unk0: u32,
@@ -88,12 +218,36 @@ pub struct IndexizedBRCAD {
     pub unk1: u16,
     pub sprites: BTreeMap<u16, brcad::Sprite>,
     pub unk2: u16,
-    pub animations: Vec<brcad::Animation>,
+    pub animations: IndexizedAnimations<brcad::Animation<SpriteRef>>,
 }
 
 impl Indexizable for brcad::BRCAD {
     type Indexized = IndexizedBRCAD;
     fn to_indexized(self) -> Self::Indexized {
+        let names = SpriteNames::new(
+            (0..)
+                .zip(&self.sprites)
+                .map(|(i, c)| (i, c.name.as_deref())),
+        );
+        let animations = self
+            .animations
+            .into_iter()
+            .map(|anim| brcad::Animation {
+                name: anim.name,
+                unk: anim.unk,
+                steps: anim
+                    .steps
+                    .into_iter()
+                    .map(|step| {
+                        let sprite = self.sprites.get(step.sprite as usize);
+                        let sprite =
+                            names.to_ref(step.sprite, sprite.and_then(|c| c.name.as_deref()));
+                        step.with_sprite(sprite)
+                    })
+                    .collect(),
+            })
+            .collect();
+
         let mut sprites = BTreeMap::new();
 
         for (i, sprite) in self.sprites.iter().enumerate() {
@@ -111,26 +265,50 @@ impl Indexizable for brcad::BRCAD {
             unk1: self.unk1,
             sprites,
             unk2: self.unk2,
-            animations: self.animations,
+            animations: IndexizedAnimations::List(animations),
         }
     }
 
-    fn from_indexized(og: Self::Indexized) -> Self {
+    fn to_indexized_named(self) -> Self::Indexized {
+        let mut indexized = self.to_indexized();
+        indexized.animations = IndexizedAnimations::named(indexized.animations.into_vec());
+        indexized
+    }
+
+    fn from_indexized(og: Self::Indexized) -> Result<Self, Error> {
+        let names = SpriteNames::new(og.sprites.iter().map(|(i, c)| (*i, c.name.as_deref())));
+        let mut animations = vec![];
+        for anim in og.animations.into_vec() {
+            let mut steps = vec![];
+            for step in anim.steps {
+                let sprite = names.resolve(step.sprite.clone())?;
+                steps.push(step.with_sprite(sprite));
+            }
+            animations.push(brcad::Animation {
+                name: anim.name,
+                unk: anim.unk,
+                steps,
+            });
+        }
+
         let mut sprites = vec![];
 
-        for i in 0..=*og.sprites.keys().max().unwrap() {
-            let sprite = match og.sprites.get(&i) {
-                Some(c) => c.clone(),
-                None => brcad::Sprite {
-                    parts: vec![],
-                    unk: 0,
-                },
-            };
-            sprites.push(sprite);
+        if let Some(max) = og.sprites.keys().max() {
+            for i in 0..=*max {
+                let sprite = match og.sprites.get(&i) {
+                    Some(c) => c.clone(),
+                    None => brcad::Sprite {
+                        name: None,
+                        parts: vec![],
+                        unk: 0,
+                    },
+                };
+                sprites.push(sprite);
+            }
         }
 
         #[allow(deprecated)]
-        Self {
+        Ok(Self {
             timestamp: og.timestamp,
             unk0: og.unk0,
             spritesheet_num: og.spritesheet_num,
@@ -140,7 +318,324 @@ impl Indexizable for brcad::BRCAD {
             unk1: og.unk1,
             sprites,
             unk2: og.unk2,
-            animations: og.animations,
+            animations,
+        })
+    }
+}
+
+impl Serialize for SpriteRef {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::Index(c) => serializer.serialize_u16(*c),
+            Self::Name(c) => serializer.serialize_str(c),
+        }
+    }
+}
+
+struct SpriteRefVisitor;
+
+const SPRITE_REF_EXPECTED: &str = "a sprite index or a sprite name";
+
+impl<'de> Visitor<'de> for SpriteRefVisitor {
+    type Value = SpriteRef;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{}", SPRITE_REF_EXPECTED)
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+        let Ok(v) = v.try_into() else {
+            Err(E::invalid_value(
+                Unexpected::Unsigned(v),
+                &SPRITE_REF_EXPECTED,
+            ))?
+        };
+        Ok(SpriteRef::Index(v))
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+        let Ok(v) = v.try_into() else {
+            Err(E::invalid_value(
+                Unexpected::Signed(v),
+                &SPRITE_REF_EXPECTED,
+            ))?
+        };
+        Ok(SpriteRef::Index(v))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        Ok(SpriteRef::Name(v.to_string()))
+    }
+}
+
+impl<'de> Deserialize<'de> for SpriteRef {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(SpriteRefVisitor)
+    }
+}
+
+#[cfg(feature = "schema")]
+impl schemars::JsonSchema for SpriteRef {
+    fn schema_name() -> std::borrow::Cow<'static, str> {
+        "SpriteRef".into()
+    }
+
+    fn json_schema(gen: &mut schemars::SchemaGenerator) -> schemars::Schema {
+        schemars::json_schema!({
+            "anyOf": [gen.subschema_for::<u16>(), gen.subschema_for::<String>()],
+        })
+    }
+}
+
+/// The animations of an indexized BXCAD
+///
+/// When indexized by name, animations are (de)serialized as a map of the animation's name to the rest of its
+/// data, in the same order they have in the BXCAD. When deserializing, both forms are accepted.
+#[derive(Clone)]
+pub enum IndexizedAnimations<A> {
+    /// Animations are stored as a list, same as in the standard BXCAD
+    List(Vec<A>),
+    /// Animations are stored as a map, keyed by name
+    Named(Vec<A>),
+}
+
+impl<A: NamedAnimation> IndexizedAnimations<A> {
+    /// Indexize the animations by name, unless some of them share a key, in which case they're
+    /// kept as a list so that none of them gets lost
+    pub fn named(animations: Vec<A>) -> Self {
+        let mut keys = HashSet::new();
+        if animations
+            .iter()
+            .enumerate()
+            .all(|(i, c)| keys.insert(c.key(i)))
+        {
+            Self::Named(animations)
+        } else {
+            Self::List(animations)
+        }
+    }
+}
+
+impl<A> IndexizedAnimations<A> {
+    /// Get the list of animations, regardless of how they're indexized
+    pub fn into_vec(self) -> Vec<A> {
+        match self {
+            Self::List(c) | Self::Named(c) => c,
+        }
+    }
+}
+
+/// Trait for animations that can be keyed by their name in [IndexizedAnimations::Named]
+pub trait NamedAnimation: Sized {
+    /// The data in the animation other than its name
+    type Body: Serialize + DeserializeOwned;
+    /// Key used for the animation, given its position in the animation list
+    fn key(&self, index: usize) -> String;
+    /// Get the data in the animation other than its name
+    fn to_body(&self) -> Self::Body;
+    /// Build the animation back from its key and the rest of its data
+    fn from_body(key: String, body: Self::Body) -> Self;
+}
+
+/// [NamedAnimation::Body] for [bccad::Animation]
+#[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(
+    feature = "schema",
+    derive(schemars::JsonSchema),
+    schemars(rename = "BCCADAnimationBody_for_{S}")
+)]
+pub struct BCCADAnimationBody<S = u16> {
+    pub interpolation: i32,
+    pub steps: Vec<bccad::AnimationStep<S>>,
+}
+
+impl<S: Clone + Serialize + DeserializeOwned> NamedAnimation for bccad::Animation<S> {
+    type Body = BCCADAnimationBody<S>;
+    fn key(&self, _index: usize) -> String {
+        self.name.clone()
+    }
+    fn to_body(&self) -> Self::Body {
+        BCCADAnimationBody {
+            interpolation: self.interpolation,
+            steps: self.steps.clone(),
+        }
+    }
+    fn from_body(key: String, body: Self::Body) -> Self {
+        Self {
+            name: key,
+            interpolation: body.interpolation,
+            steps: body.steps,
+        }
+    }
+}
+
+/// [NamedAnimation::Body] for [brcad::Animation]
+#[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(
+    feature = "schema",
+    derive(schemars::JsonSchema),
+    schemars(rename = "BRCADAnimationBody_for_{S}")
+)]
+pub struct BRCADAnimationBody<S = u16> {
+    pub unk: u16,
+    pub steps: Vec<brcad::AnimationStep<S>>,
+}
+
+/// BRCAD animations without a label are keyed by their index instead. Labels are C identifiers,
+/// so they can never be confused with one.
+impl<S: Clone + Serialize + DeserializeOwned> NamedAnimation for brcad::Animation<S> {
+    type Body = BRCADAnimationBody<S>;
+    fn key(&self, index: usize) -> String {
+        match &self.name {
+            Some(c) => c.clone(),
+            None => index.to_string(),
+        }
+    }
+    fn to_body(&self) -> Self::Body {
+        BRCADAnimationBody {
+            unk: self.unk,
+            steps: self.steps.clone(),
+        }
+    }
+    fn from_body(key: String, body: Self::Body) -> Self {
+        let name = match key.parse::<usize>() {
+            Ok(_) => None,
+            Err(_) => Some(key),
+        };
+        Self {
+            name,
+            unk: body.unk,
+            steps: body.steps,
+        }
+    }
+}
+
+impl<A: NamedAnimation + Serialize> Serialize for IndexizedAnimations<A> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::List(anims) => anims.serialize(serializer),
+            Self::Named(anims) => {
+                let mut map = serializer.serialize_map(Some(anims.len()))?;
+                for (i, anim) in anims.iter().enumerate() {
+                    map.serialize_entry(&anim.key(i), &anim.to_body())?;
+                }
+                map.end()
+            }
         }
     }
 }
+
+struct IndexizedAnimationsVisitor<A>(PhantomData<A>);
+
+impl<'de, A: NamedAnimation + Deserialize<'de>> Visitor<'de> for IndexizedAnimationsVisitor<A> {
+    type Value = IndexizedAnimations<A>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            formatter,
+            "a list of animations or a map of animation names to animations"
+        )
+    }
+
+    fn visit_seq<S: SeqAccess<'de>>(self, mut seq: S) -> Result<Self::Value, S::Error> {
+        let mut anims = vec![];
+        while let Some(anim) = seq.next_element()? {
+            anims.push(anim);
+        }
+        Ok(IndexizedAnimations::List(anims))
+    }
+
+    fn visit_map<M: MapAccess<'de>>(self, mut map: M) -> Result<Self::Value, M::Error> {
+        let mut anims = vec![];
+        while let Some((key, body)) = map.next_entry::<String, A::Body>()? {
+            anims.push(A::from_body(key, body));
+        }
+        Ok(IndexizedAnimations::Named(anims))
+    }
+}
+
+impl<'de, A: NamedAnimation + Deserialize<'de>> Deserialize<'de> for IndexizedAnimations<A> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(IndexizedAnimationsVisitor(PhantomData))
+    }
+}
+
+#[cfg(feature = "schema")]
+impl<A: NamedAnimation + schemars::JsonSchema> schemars::JsonSchema for IndexizedAnimations<A>
+where
+    A::Body: schemars::JsonSchema,
+{
+    fn schema_name() -> std::borrow::Cow<'static, str> {
+        format!("IndexizedAnimations_for_{}", A::schema_name()).into()
+    }
+
+    fn json_schema(gen: &mut schemars::SchemaGenerator) -> schemars::Schema {
+        schemars::json_schema!({
+            "anyOf": [
+                gen.subschema_for::<Vec<A>>(),
+                gen.subschema_for::<BTreeMap<String, A::Body>>(),
+            ],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BCCAD, BRCAD};
+    use std::io::Cursor;
+
+    /// Indexizes by name, goes through JSON and back, like the flour binary does
+    fn round_trip<X>(bxcad: X) -> X
+    where
+        X: Indexizable,
+        X::Indexized: Serialize + DeserializeOwned,
+    {
+        let json = serde_json::to_string(&bxcad.to_indexized_named()).unwrap();
+        X::from_indexized(serde_json::from_str(&json).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn bccad_duplicate_names_are_kept() {
+        let mut bccad = BCCAD::from_binary(&mut Cursor::new(include_bytes!(
+            "../../tests/fixtures/sample.bccad"
+        )))
+        .unwrap();
+        let first = bccad.animations[0].clone();
+        bccad.animations.push(first);
+        let names: Vec<_> = bccad.animations.iter().map(|c| c.name.clone()).collect();
+
+        let bccad = round_trip(bccad);
+        let round_tripped: Vec<_> = bccad.animations.iter().map(|c| c.name.clone()).collect();
+        assert_eq!(round_tripped, names);
+    }
+
+    #[test]
+    fn brcad_duplicate_labels_are_kept() {
+        let mut brcad = BRCAD::from_binary(&mut Cursor::new(include_bytes!(
+            "../../tests/fixtures/sample.brcad"
+        )))
+        .unwrap();
+        for anim in &mut brcad.animations {
+            anim.name = Some("SAME_LABEL".to_string());
+        }
+        let count = brcad.animations.len();
+
+        let brcad = round_trip(brcad);
+        assert_eq!(brcad.animations.len(), count);
+        assert!(brcad
+            .animations
+            .iter()
+            .all(|c| c.name.as_deref() == Some("SAME_LABEL")));
+    }
+
+    #[test]
+    fn unique_names_are_indexized() {
+        let bccad = BCCAD::from_binary(&mut Cursor::new(include_bytes!(
+            "../../tests/fixtures/sample.bccad"
+        )))
+        .unwrap();
+        let json = serde_json::to_value(bccad.to_indexized_named()).unwrap();
+        assert!(json["animations"].is_object());
+    }
+}
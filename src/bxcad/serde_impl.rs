@@ -1,8 +1,191 @@
+/// Neutral values for fields that can be left out of the JSON, for the ones where it's not
+/// the type's [Default]
+pub mod defaults {
+    use crate::Color;
+
+    pub fn scale() -> f32 {
+        1.0
+    }
+
+    pub fn opacity<T: From<u8>>() -> T {
+        T::from(255)
+    }
+
+    pub fn multiply_color() -> Color {
+        Color {
+            red: 255,
+            blue: 255,
+            green: 255,
+        }
+    }
+}
+
+/// Implementation for Color deserialization from either an object or a hex string in flour 2.1+
+mod color {
+    use crate::Color;
+    use serde::{
+        de::{value::MapAccessDeserializer, Error, MapAccess, Unexpected, Visitor},
+        Deserialize, Deserializer,
+    };
+
+    /// The object form, which is what Color used to derive
+    #[derive(Deserialize)]
+    #[serde(rename = "Color")]
+    struct ColorObject {
+        red: u8,
+        blue: u8,
+        green: u8,
+    }
+
+    struct ColorVisitor;
+
+    impl<'de> Visitor<'de> for ColorVisitor {
+        type Value = Color;
+
+        fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(
+                formatter,
+                "a \"#rrggbb\" string or an object with red, blue and green"
+            )
+        }
+
+        fn visit_str<E: Error>(self, v: &str) -> Result<Self::Value, E> {
+            Color::from_hex(v).ok_or_else(|| E::invalid_value(Unexpected::Str(v), &self))
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+            let c = ColorObject::deserialize(MapAccessDeserializer::new(map))?;
+            Ok(Color {
+                red: c.red,
+                blue: c.blue,
+                green: c.green,
+            })
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Color {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserializer.deserialize_any(ColorVisitor)
+        }
+    }
+
+    #[cfg(feature = "schema")]
+    impl schemars::JsonSchema for Color {
+        fn schema_name() -> std::borrow::Cow<'static, str> {
+            "Color".into()
+        }
+
+        fn json_schema(_: &mut schemars::SchemaGenerator) -> schemars::Schema {
+            let channel = serde_json::json!({"type": "integer", "minimum": 0, "maximum": 255});
+            schemars::json_schema!({
+                "description": "RGB color",
+                "anyOf": [
+                    {
+                        "type": "object",
+                        "required": ["red", "blue", "green"],
+                        "properties": {"red": channel, "blue": channel, "green": channel},
+                    },
+                    {"type": "string", "pattern": "^#?[0-9a-fA-F]{6}$"},
+                ],
+            })
+        }
+    }
+}
+
+/// Implementation for bit-exact f32 (de)serialization in flour 2.1+
+///
+/// Finite values are written as the shortest number that reads back to the exact same bits.
+/// Non-finite values can't be JSON numbers, so they're written as the hex string of their bits
+/// (like `"0x7fc00000"`), which keeps NaN payloads. Hex strings can also be used for finite
+/// values, and `"NaN"`, `"inf"` and `"-inf"` are accepted too.
+pub mod float {
+    use serde::{
+        de::{Error, Unexpected, Visitor},
+        Deserializer, Serializer,
+    };
+
+    /// The bits of a float, to compare and hash floats by
+    ///
+    /// Comparing bits instead of values means that equal sprite parts and such are the ones that
+    /// would produce the exact same bytes in a BXCAD file, so `0.0` and `-0.0` are different and
+    /// NaN is equal to itself.
+    pub fn bits(value: f32) -> u32 {
+        value.to_bits()
+    }
+
+    struct FloatVisitor;
+
+    const EXPECTED: &str = "a number or a hex string with the bits of a 32-bit float";
+
+    impl<'de> Visitor<'de> for FloatVisitor {
+        type Value = f32;
+
+        fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(formatter, "{}", EXPECTED)
+        }
+
+        fn visit_f64<E>(self, v: f64) -> Result<Self::Value, E> {
+            Ok(v as f32)
+        }
+
+        fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E> {
+            Ok(v as f32)
+        }
+
+        fn visit_i64<E>(self, v: i64) -> Result<Self::Value, E> {
+            Ok(v as f32)
+        }
+
+        fn visit_str<E: Error>(self, v: &str) -> Result<Self::Value, E> {
+            if let Some(hex) = v.strip_prefix("0x").or_else(|| v.strip_prefix("0X")) {
+                if let Ok(bits) = u32::from_str_radix(hex, 16) {
+                    return Ok(f32::from_bits(bits));
+                }
+            }
+            match v.to_ascii_lowercase().as_str() {
+                "nan" => Ok(f32::NAN),
+                "inf" | "infinity" => Ok(f32::INFINITY),
+                "-inf" | "-infinity" => Ok(f32::NEG_INFINITY),
+                _ => Err(E::invalid_value(Unexpected::Str(v), &EXPECTED)),
+            }
+        }
+    }
+
+    pub fn serialize<S: Serializer>(value: &f32, serializer: S) -> Result<S::Ok, S::Error> {
+        if value.is_finite() {
+            // serializing as f64 the shortest decimal that reads back as this value, so that it
+            // doesn't get printed with the extra digits of its f64 conversion when it goes
+            // through serde_json::Value
+            let shortest: f64 = value.to_string().parse().unwrap();
+            serializer.serialize_f64(shortest)
+        } else {
+            serializer.serialize_str(&format!("{:#010x}", value.to_bits()))
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f32, D::Error> {
+        deserializer.deserialize_any(FloatVisitor)
+    }
+
+    #[cfg(feature = "schema")]
+    pub fn schema(_: &mut schemars::SchemaGenerator) -> schemars::Schema {
+        schemars::json_schema!({
+            "anyOf": [
+                {"type": "number"},
+                {
+                    "type": "string",
+                    "pattern": "^(0[xX][0-9a-fA-F]+|-?[iI][nN][fF]([iI][nN][iI][tT][yY])?|[nN][aA][nN])$",
+                },
+            ],
+        })
+    }
+}
+
 /// Implementation for BRCAD AnimationStep pos_x and pos_y serialization in flour 2.1+
 pub mod pos_xy {
     use crate::bxcad::brcad::AnimationStep;
     use serde::{
-        de::{Error, SeqAccess, Unexpected, Visitor},
+        de::{Error, IgnoredAny, SeqAccess, Unexpected, Visitor},
         ser::SerializeSeq,
         Deserializer, Serializer,
     };
@@ -54,11 +237,10 @@ pub mod pos_xy {
                 Err(A::Error::invalid_length(1, &"2"))?
             };
 
-            // this way we can catch some errors where there's too many values
-            if let Some(size) = seq.size_hint() {
-                if size != 2 {
-                    Err(A::Error::invalid_length(size + 2, &"2"))?;
-                }
+            // the size hint counts the elements that are left, which might not be known, so check for
+            // a third one instead
+            if seq.next_element::<IgnoredAny>()?.is_some() {
+                Err(A::Error::invalid_length(3, &"2"))?;
             }
 
             *AnimationStep::get_pos_mut(&mut out, false) = pos_x;
@@ -77,6 +259,17 @@ pub mod pos_xy {
     pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
         deserializer.deserialize_any(PosXYVisitor)
     }
+
+    #[cfg(feature = "schema")]
+    pub fn schema(_: &mut schemars::SchemaGenerator) -> schemars::Schema {
+        let pos = serde_json::json!({"type": "integer", "minimum": i16::MIN, "maximum": i16::MAX});
+        schemars::json_schema!({
+            "anyOf": [
+                {"type": "array", "items": pos, "minItems": 2, "maxItems": 2},
+                {"type": "integer", "minimum": i32::MIN, "maximum": u32::MAX},
+            ],
+        })
+    }
 }
 
 /// Implementation for BRCAD has_texture serialization in flour 2.1+
@@ -125,25 +318,35 @@ pub mod use_variation {
         // 2.1+ behavior
 
         fn visit_bool<E: Error>(self, v: bool) -> Result<Self::Value, E> {
-            if cfg!(target_endian = "big") {
-                Ok(v as u32)
-            } else {
-                Ok((v as u32) << 24)
-            }
+            Ok((v as u32) << 24)
         }
     }
 
+    /// The value of `has_variations` that a raw `unk0` stands for, which is whether its first
+    /// byte in the file is set
+    ///
+    /// BRCADs are big endian, so that's the most significant byte on any machine.
+    pub fn decode(value: u32) -> bool {
+        value >> 24 != 0
+    }
+
     pub fn serialize<S: Serializer>(value: &u32, serializer: S) -> Result<S::Ok, S::Error> {
-        if cfg!(target_endian = "big") {
-            serializer.serialize_bool(*value as u8 != 0)
-        } else {
-            serializer.serialize_bool((*value >> 24) as u8 != 0)
-        }
+        serializer.serialize_bool(decode(*value))
     }
 
     pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
         deserializer.deserialize_any(UseVariationVisitor)
     }
+
+    #[cfg(feature = "schema")]
+    pub fn schema(_: &mut schemars::SchemaGenerator) -> schemars::Schema {
+        schemars::json_schema!({
+            "anyOf": [
+                {"type": "boolean"},
+                {"type": "integer", "minimum": i32::MIN, "maximum": u32::MAX},
+            ],
+        })
+    }
 }
 
 pub mod variation_num {
@@ -178,15 +381,94 @@ pub mod variation_num {
         }
     }
 
-    pub fn serialize<S: Serializer>(value: &u32, serializer: S) -> Result<S::Ok, S::Error> {
+    /// The value of `variation_num` that a raw `unk` stands for
+    pub fn decode(value: u32) -> u16 {
         if cfg!(target_endian = "big") {
-            serializer.serialize_u16(*value as u16)
+            value as u16
         } else {
-            serializer.serialize_u16((*value >> 16) as u16)
+            (value >> 16) as u16
         }
     }
 
+    pub fn serialize<S: Serializer>(value: &u32, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u16(decode(*value))
+    }
+
     pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
         deserializer.deserialize_any(VariationNumVisitor)
     }
+
+    #[cfg(feature = "schema")]
+    pub fn schema(_: &mut schemars::SchemaGenerator) -> schemars::Schema {
+        schemars::json_schema!({"type": "integer"})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::float::bits;
+    use crate::{BCCAD, BXCAD};
+    use std::io::Cursor;
+
+    const SPECIAL_FLOATS: [f32; 9] = [
+        -0.0,
+        f32::INFINITY,
+        f32::NEG_INFINITY,
+        f32::NAN,
+        // NaN with a payload, and a negative one
+        f32::from_bits(0x7fc0_1234),
+        f32::from_bits(0xff80_0001),
+        // subnormals
+        f32::from_bits(0x0000_0001),
+        f32::from_bits(0x807f_ffff),
+        // the one right before the smallest normal
+        f32::from_bits(0x007f_fffe),
+    ];
+
+    fn with_floats(value: f32) -> BCCAD {
+        let mut bccad = BCCAD::from_binary(&mut Cursor::new(include_bytes!(
+            "../../tests/fixtures/sample.bccad"
+        )))
+        .unwrap();
+        let part = &mut bccad.sprites[0].parts[0];
+        part.scale_x = value;
+        part.rotation = value;
+        part.depth.top_left = value;
+        bccad.animations[0].steps[0].depth = value;
+        bccad
+    }
+
+    fn floats(bccad: &BCCAD) -> [u32; 4] {
+        let part = &bccad.sprites[0].parts[0];
+        [
+            bits(part.scale_x),
+            bits(part.rotation),
+            bits(part.depth.top_left),
+            bits(bccad.animations[0].steps[0].depth),
+        ]
+    }
+
+    #[test]
+    fn floats_round_trip_through_json() {
+        for value in SPECIAL_FLOATS {
+            let bccad = with_floats(value);
+            let json = serde_json::to_string(&bccad).unwrap();
+            let read: BCCAD = serde_json::from_str(&json).unwrap();
+            assert_eq!(floats(&read), [bits(value); 4], "{:#010x}", bits(value));
+
+            let value_ = serde_json::to_value(&bccad).unwrap();
+            let read: BCCAD = serde_json::from_value(value_).unwrap();
+            assert_eq!(floats(&read), [bits(value); 4], "{:#010x}", bits(value));
+        }
+    }
+
+    #[test]
+    fn floats_round_trip_through_binary() {
+        for value in SPECIAL_FLOATS {
+            let mut data = vec![];
+            with_floats(value).to_binary(&mut data).unwrap();
+            let read = BCCAD::from_binary(&mut Cursor::new(data)).unwrap();
+            assert_eq!(floats(&read), [bits(value); 4], "{:#010x}", bits(value));
+        }
+    }
 }
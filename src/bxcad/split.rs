@@ -0,0 +1,177 @@
+use serde_json::Value;
+use std::collections::HashSet;
+
+use crate::error::Result;
+
+/// Name of the manifest file of a split directory, without its extension
+pub const MANIFEST: &str = "manifest";
+/// Directory with the files of the sprites, inside a split directory
+pub const SPRITES_DIR: &str = "sprites";
+/// Directory with the files of the animations, inside a split directory
+pub const ANIMATIONS_DIR: &str = "animations";
+
+/// Splits flour JSON into a manifest plus one file per sprite and per animation
+///
+/// Returns the path of each file relative to the directory, along with its contents. The
+/// manifest goes first, and it's the same JSON but with each sprite and animation replaced by
+/// the path to its file, so their order (or their keys, when indexized) is kept there.
+///
+/// Files are named after their sprite or animation when it has a name that works as a file
+/// name on every platform and isn't shared with another one, and after their index otherwise.
+pub fn split(mut wrapper: Value, extension: &str) -> Vec<(String, Value)> {
+    let mut files = vec![];
+    if let Some(data) = wrapper.get_mut("data") {
+        for (field, dir) in [("sprites", SPRITES_DIR), ("animations", ANIMATIONS_DIR)] {
+            if let Some(elements) = data.get_mut(field) {
+                split_elements(elements, dir, extension, &mut files);
+            }
+        }
+    }
+    files.insert(0, (format!("{}.{}", MANIFEST, extension), wrapper));
+    files
+}
+
+/// Puts back together flour JSON made by [split], given its manifest and a function that reads
+/// the file at one of the paths in it
+pub fn join(manifest: &mut Value, mut read: impl FnMut(&str) -> Result<Value>) -> Result<()> {
+    let Some(data) = manifest.get_mut("data") else {
+        return Ok(());
+    };
+    for field in ["sprites", "animations"] {
+        let elements: Box<dyn Iterator<Item = &mut Value>> = match data.get_mut(field) {
+            Some(Value::Array(c)) => Box::new(c.iter_mut()),
+            Some(Value::Object(c)) => Box::new(c.values_mut()),
+            _ => continue,
+        };
+        for element in elements {
+            if let Value::String(path) = element {
+                *element = read(path)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn split_elements(
+    elements: &mut Value,
+    dir: &str,
+    extension: &str,
+    files: &mut Vec<(String, Value)>,
+) {
+    // indexized sprites and named animations are maps, the rest are lists
+    let entries: Vec<(Option<String>, &mut Value)> = match elements {
+        Value::Array(c) => c.iter_mut().map(|c| (None, c)).collect(),
+        Value::Object(c) => c.iter_mut().map(|(k, v)| (Some(k.clone()), v)).collect(),
+        _ => return,
+    };
+    let names: Vec<Option<String>> = entries
+        .iter()
+        .map(|(key, element)| {
+            element
+                .get("name")
+                .and_then(Value::as_str)
+                .map(str::to_string)
+                .or_else(|| key.clone())
+                .filter(|c| is_file_name(c))
+        })
+        .collect();
+
+    // file systems may not tell apart names that only differ in case
+    let mut seen = HashSet::new();
+    let mut repeated = HashSet::new();
+    for name in names.iter().flatten() {
+        if !seen.insert(name.to_lowercase()) {
+            repeated.insert(name.to_lowercase());
+        }
+    }
+
+    for (i, ((_, element), name)) in entries.into_iter().zip(names).enumerate() {
+        let stem = match name {
+            Some(c) if !repeated.contains(&c.to_lowercase()) => c,
+            _ => i.to_string(),
+        };
+        let path = format!("{}/{}.{}", dir, stem, extension);
+        files.push((path.clone(), element.take()));
+        *element = Value::String(path);
+    }
+}
+
+/// Whether a sprite or animation name can be used as is for the name of its file
+///
+/// Names made only of digits are left for the files named after their index, and names of
+/// devices on Windows can't be used even with an extension.
+fn is_file_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 64
+        && !name.bytes().all(|c| c.is_ascii_digit())
+        && name
+            .bytes()
+            .all(|c| c.is_ascii_alphanumeric() || c == b'_' || c == b'-')
+        && !is_windows_device(name)
+}
+
+fn is_windows_device(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    match name.as_bytes() {
+        [b'c', b'o', b'm', b'1'..=b'9'] | [b'l', b'p', b't', b'1'..=b'9'] => true,
+        _ => ["con", "prn", "aux", "nul"].contains(&name.as_str()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    #[test]
+    fn split_join_round_trip() {
+        let wrapper = json!({
+            "bxcad_type": "BCCAD",
+            "data": {
+                "sprites": [
+                    {"name": "hand", "parts": []},
+                    {"name": "hand", "parts": [1]},
+                    {"name": "Com1", "parts": [2]},
+                    {"name": "LPT9", "parts": [3]},
+                    {"name": "nul", "parts": [4]},
+                    {"name": "console", "parts": [5]},
+                ],
+                "animations": {
+                    "Beat": {"steps": []},
+                    "beat": {"steps": [1]},
+                    "42": {"steps": [2]},
+                    "7": {"steps": [3]},
+                    "Aux": {"steps": [4]},
+                    "idle": {"steps": [5]},
+                },
+            },
+        });
+        let files = split(wrapper.clone(), "json");
+        let paths: Vec<&str> = files.iter().map(|(path, _)| path.as_str()).collect();
+        assert_eq!(
+            paths,
+            [
+                "manifest.json",
+                "sprites/0.json",
+                "sprites/1.json",
+                "sprites/2.json",
+                "sprites/3.json",
+                "sprites/4.json",
+                "sprites/console.json",
+                "animations/0.json",
+                "animations/1.json",
+                "animations/2.json",
+                "animations/3.json",
+                "animations/4.json",
+                "animations/idle.json",
+            ]
+        );
+
+        let mut files: HashMap<String, Value> = files.into_iter().collect();
+        let mut manifest = files.remove("manifest.json").unwrap();
+        join(&mut manifest, |path| Ok(files.remove(path).unwrap())).unwrap();
+        assert_eq!(manifest, wrapper);
+        assert!(files.is_empty());
+    }
+}
@@ -0,0 +1,328 @@
+use std::{collections::HashMap, fmt};
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{error::Result, BXCAD};
+
+/// Number of values of each field shown when displaying [FieldStats]
+const SHOWN_VALUES: usize = 8;
+/// Number of correlated fields shown when displaying [FieldStats]
+const SHOWN_CORRELATIONS: usize = 4;
+/// Correlations weaker than this aren't worth listing
+const MIN_CORRELATION: f64 = 0.1;
+/// Value of a field in the records that don't have it, like sprites without a name
+const MISSING: &str = "(missing)";
+
+/// The values of the fields of one sprite, sprite part, animation, etc., by their path inside it
+type Record = Vec<(String, String)>;
+
+/// The values found in a field across a [Corpus]
+#[derive(Clone, Debug, PartialEq)]
+pub struct FieldStats {
+    /// What the field belongs to, like `BCCAD sprites[].parts[]`
+    pub kind: String,
+    /// Path of the field inside of what it belongs to, like `unk1[3]`
+    pub field: String,
+    /// Number of times the field was found
+    pub count: usize,
+    /// Each value of the field as it'd appear in flour JSON, along with how many times it was
+    /// found, most common first
+    pub histogram: Vec<(String, usize)>,
+    /// Other fields of the same kind, and how much knowing their value tells about the value
+    /// of this field, from 0 (nothing) to 1 (everything), strongest first
+    pub correlations: Vec<(String, f64)>,
+}
+
+impl FieldStats {
+    /// Whether the field is one that flour doesn't know the purpose of yet
+    pub fn is_unknown(&self) -> bool {
+        is_unknown(&self.field)
+    }
+}
+
+impl fmt::Display for FieldStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let separator = if self.kind.contains(' ') { "." } else { " " };
+        write!(f, "{}{}{}: ", self.kind, separator, self.field)?;
+        if let [(value, _)] = self.histogram.as_slice() {
+            return writeln!(f, "always {} ({})", value, times(self.count));
+        }
+        writeln!(
+            f,
+            "{} different values ({})",
+            self.histogram.len(),
+            times(self.count)
+        )?;
+        for (value, count) in self.histogram.iter().take(SHOWN_VALUES) {
+            let percent = *count as f64 * 100.0 / self.count as f64;
+            writeln!(f, "    {:>6} ({:5.1}%)  {}", count, percent, value)?;
+        }
+        if self.histogram.len() > SHOWN_VALUES {
+            writeln!(f, "    ... {} more", self.histogram.len() - SHOWN_VALUES)?;
+        }
+        let correlations: Vec<_> = self
+            .correlations
+            .iter()
+            .take_while(|(_, c)| *c >= MIN_CORRELATION)
+            .take(SHOWN_CORRELATIONS)
+            .map(|(field, c)| format!("{} ({:.2})", field, c))
+            .collect();
+        if !correlations.is_empty() {
+            writeln!(f, "    correlated with {}", correlations.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// A collection of BXCADs to gather statistics about the values of their fields
+///
+/// Each sprite, sprite part, animation and animation step is a record, and records of the same
+/// kind are compared with each other, so that a field can be checked against the rest of the
+/// fields next to it. Arrays of numbers, like `unk1`, are split into a field per element.
+#[derive(Clone, Debug, Default)]
+pub struct Corpus {
+    /// Records of each kind, like `BCCAD sprites[].parts[]`, in the order they were found
+    kinds: Vec<(String, Vec<Record>)>,
+}
+
+impl Corpus {
+    /// Adds the sprites, animations and the rest of the records of a BXCAD to the corpus
+    pub fn add<X: BXCAD + Serialize>(&mut self, bxcad: &X) -> Result<()> {
+        let value = serde_json::to_value(bxcad)?;
+        let kind = format!("{:?}", X::BXCAD_TYPE);
+        self.add_record(kind, &value);
+        Ok(())
+    }
+
+    /// Statistics for every field, or only those of unknown purpose
+    ///
+    /// Fields of unknown purpose are those named `unk` something.
+    pub fn stats(&self, unknowns_only: bool) -> Vec<FieldStats> {
+        let mut out = vec![];
+        for (kind, records) in &self.kinds {
+            let columns = columns(records);
+            for (field, values) in &columns {
+                if unknowns_only && !is_unknown(field) {
+                    continue;
+                }
+                let mut histogram: Vec<(String, usize)> = counts(values)
+                    .into_iter()
+                    .map(|(value, count)| (value.to_string(), count))
+                    .collect();
+                histogram.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+                let mut correlations = vec![];
+                if histogram.len() > 1 {
+                    for (other, other_values) in &columns {
+                        if other == field {
+                            continue;
+                        }
+                        if let Some(c) = uncertainty_coefficient(values, other_values) {
+                            correlations.push((other.clone(), c));
+                        }
+                    }
+                    correlations.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+                }
+
+                out.push(FieldStats {
+                    kind: kind.clone(),
+                    field: field.clone(),
+                    count: values.len(),
+                    histogram,
+                    correlations,
+                });
+            }
+        }
+        out
+    }
+
+    fn add_record(&mut self, kind: String, value: &Value) {
+        let mut record = Record::new();
+        let mut children = vec![];
+        flatten(&mut record, &mut children, "", value);
+        match self.kinds.iter_mut().find(|(c, _)| *c == kind) {
+            Some((_, records)) => records.push(record),
+            None => self.kinds.push((kind.clone(), vec![record])),
+        }
+        for (path, child) in children {
+            let separator = if kind.contains(' ') { "." } else { " " };
+            self.add_record(format!("{}{}{}[]", kind, separator, path), child);
+        }
+    }
+}
+
+/// Adds the fields of a value to a record, and collects the records inside of it
+fn flatten<'a>(
+    record: &mut Record,
+    children: &mut Vec<(String, &'a Value)>,
+    path: &str,
+    value: &'a Value,
+) {
+    let join = |key: &str| match path {
+        "" => key.to_string(),
+        _ => format!("{}.{}", path, key),
+    };
+    match value {
+        Value::Object(object) => {
+            for (key, c) in object {
+                flatten(record, children, &join(key), c);
+            }
+        }
+        Value::Array(array) if array.iter().any(Value::is_object) => {
+            children.extend(array.iter().map(|c| (path.to_string(), c)));
+        }
+        Value::Array(array) => {
+            for (i, c) in array.iter().enumerate() {
+                flatten(record, children, &format!("{}[{}]", path, i), c);
+            }
+        }
+        _ => {
+            record.push((path.to_string(), value.to_string()));
+        }
+    }
+}
+
+/// The values of each field across records, in the same order as the records
+fn columns(records: &[Record]) -> Vec<(String, Vec<&str>)> {
+    let mut columns: Vec<(String, Vec<&str>)> = vec![];
+    let mut positions = HashMap::new();
+    for (i, record) in records.iter().enumerate() {
+        for (field, value) in record {
+            let position = *positions.entry(field.as_str()).or_insert_with(|| {
+                columns.push((field.clone(), vec![MISSING; i]));
+                columns.len() - 1
+            });
+            columns[position].1.push(value);
+        }
+        for (_, values) in columns.iter_mut() {
+            values.resize(i + 1, MISSING);
+        }
+    }
+    columns
+}
+
+fn counts<'a>(values: &[&'a str]) -> HashMap<&'a str, usize> {
+    let mut counts = HashMap::new();
+    for value in values {
+        *counts.entry(*value).or_insert(0) += 1;
+    }
+    counts
+}
+
+fn entropy(counts: impl Iterator<Item = usize>, total: usize) -> f64 {
+    counts
+        .map(|c| {
+            let p = c as f64 / total as f64;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+/// How much knowing the value of `given` tells about the value of `field`, as the fraction of
+/// the entropy of `field` that goes away
+///
+/// Fields with a different value in most records, like positions, trivially tell everything
+/// about anything else in small corpora, so they're skipped.
+fn uncertainty_coefficient(field: &[&str], given: &[&str]) -> Option<f64> {
+    let total = field.len();
+    let given_counts = counts(given);
+    if given_counts.len() < 2 || given_counts.len() * 2 > total {
+        return None;
+    }
+    let mut joint: HashMap<(&str, &str), usize> = HashMap::new();
+    for (a, b) in field.iter().zip(given) {
+        *joint.entry((a, b)).or_insert(0) += 1;
+    }
+    let h_field = entropy(counts(field).into_values(), total);
+    let h_given = entropy(given_counts.into_values(), total);
+    let h_joint = entropy(joint.into_values(), total);
+    // H(field | given) = H(field, given) - H(given)
+    Some((h_field - (h_joint - h_given)) / h_field)
+}
+
+fn times(count: usize) -> String {
+    match count {
+        1 => "once".to_string(),
+        c => format!("{} times", c),
+    }
+}
+
+fn is_unknown(field: &str) -> bool {
+    field
+        .rsplit('.')
+        .next()
+        .is_some_and(|c| c.starts_with("unk"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BCCAD;
+    use std::io::Cursor;
+
+    fn sample() -> BCCAD {
+        BCCAD::from_binary(&mut Cursor::new(include_bytes!(
+            "../../tests/fixtures/sample.bccad"
+        )))
+        .unwrap()
+    }
+
+    fn find<'a>(stats: &'a [FieldStats], kind: &str, field: &str) -> &'a FieldStats {
+        stats
+            .iter()
+            .find(|c| c.kind == kind && c.field == field)
+            .unwrap()
+    }
+
+    #[test]
+    fn histogram() {
+        let mut corpus = Corpus::default();
+        corpus.add(&sample()).unwrap();
+        corpus.add(&sample()).unwrap();
+        let stats = corpus.stats(false);
+
+        let duration = find(&stats, "BCCAD animations[].steps[]", "duration");
+        assert_eq!(duration.count, 6);
+        let histogram = [("2", 2), ("4", 2), ("8", 2)].map(|(c, n)| (c.to_string(), n));
+        assert_eq!(duration.histogram, histogram);
+
+        let width = find(&stats, "BCCAD", "texture_width");
+        assert_eq!(width.histogram, [("512".to_string(), 2)]);
+        assert_eq!(
+            width.to_string(),
+            "BCCAD texture_width: always 512 (2 times)\n"
+        );
+
+        assert!(corpus.stats(true).iter().all(FieldStats::is_unknown));
+    }
+
+    #[test]
+    fn correlation() {
+        let mut bccad = sample();
+        bccad.sprites.truncate(1);
+        let part = bccad.sprites[0].parts[0].clone();
+        bccad.sprites[0].parts = (0..8)
+            .map(|i| {
+                let mut part = part.clone();
+                // opacity depends on flip_x, and has nothing to do with flip_y
+                part.flip_x = i % 2 == 0;
+                part.flip_y = i / 2 % 2 == 0;
+                part.opacity = if part.flip_x { 255 } else { 128 };
+                part
+            })
+            .collect();
+        let mut corpus = Corpus::default();
+        corpus.add(&bccad).unwrap();
+        let stats = corpus.stats(false);
+
+        let opacity = find(&stats, "BCCAD sprites[].parts[]", "opacity");
+        assert_eq!(
+            opacity.correlations,
+            [("flip_x".to_string(), 1.0), ("flip_y".to_string(), 0.0)]
+        );
+        assert!(opacity
+            .to_string()
+            .ends_with("    correlated with flip_x (1.00)\n"));
+    }
+}
@@ -0,0 +1,152 @@
+use semver::Version;
+use serde_json::Value;
+
+use crate::{
+    bxcad::OLDEST_SUPPORTED_VERSION,
+    error::{Error, Result},
+};
+
+/// Oldest flour version whose JSON files can be upgraded with [upgrade]
+pub const OLDEST_UPGRADABLE_VERSION: &str = "1.0.0";
+
+/// Checks whether flour JSON made with the given flour version can be read by this version as-is
+///
+/// Files from newer versions of flour are never accepted, since there's no way to know what changed.
+/// Files from versions older than [OLDEST_SUPPORTED_VERSION] have to go through [upgrade] first.
+pub fn check_version(version: &str) -> Result<()> {
+    let version = parse_version(version)?;
+    if version > current_version() {
+        return Err(Error::NewerVersion(version));
+    }
+    if version < Version::parse(OLDEST_SUPPORTED_VERSION)? {
+        return Err(Error::IncompatibleVersion(version));
+    }
+    Ok(())
+}
+
+/// Upgrades the JSON for a [BXCADWrapper](crate::bxcad::BXCADWrapper) made with an older flour version
+/// so that it follows the format used by the current version, and updates its `flour_version` to match
+///
+/// Returns the flour version the JSON was originally made with.
+///
+/// Changes made to the format, by version:
+/// - **2.1.0**: the BRCAD fields `unk0` (in the BRCAD itself), `unk` (in sprite parts) and `unk0`
+///   (in animation steps) were replaced by `has_variations`, `variation_num` and `pos`, respectively.
+///   Files made before this version store the raw integers.
+pub fn upgrade(wrapper: &mut Value) -> Result<Version> {
+    let Some(version) = wrapper.get("flour_version").and_then(Value::as_str) else {
+        Err(Error::NotFlour)?
+    };
+    let version = parse_version(version)?;
+    if version > current_version() {
+        return Err(Error::NewerVersion(version));
+    }
+    if version < Version::parse(OLDEST_UPGRADABLE_VERSION)? {
+        return Err(Error::IncompatibleVersion(version));
+    }
+
+    let is_brcad = wrapper.get("bxcad_type").and_then(Value::as_str) == Some("BRCAD");
+    let indexized = wrapper.get("indexize").and_then(Value::as_bool) == Some(true);
+    let Some(data) = wrapper.get_mut("data") else {
+        Err(Error::NotFlour)?
+    };
+
+    if version < Version::new(2, 1, 0) && is_brcad {
+        upgrade_brcad_raw_fields(data, indexized);
+    }
+
+    wrapper["flour_version"] = Value::from(env!("CARGO_PKG_VERSION"));
+    Ok(version)
+}
+
+fn current_version() -> Version {
+    Version::parse(env!("CARGO_PKG_VERSION")).unwrap()
+}
+
+fn parse_version(version: &str) -> Result<Version> {
+    Version::parse(version).map_err(|_| Error::BadFlourVersion(version.to_string()))
+}
+
+/// Upgrade for 2.1.0. Raw values are the integers read from the file, so their first bytes in the
+/// file are their most significant ones
+fn upgrade_brcad_raw_fields(data: &mut Value, indexized: bool) {
+    // the indexized BRCAD still uses the raw value here
+    if !indexized {
+        rename_raw(data, "unk0", "has_variations", |raw| {
+            Value::from((raw >> 24) as u8 != 0)
+        });
+    }
+
+    let sprites = match data.get_mut("sprites") {
+        Some(Value::Array(c)) => c.iter_mut().collect(),
+        Some(Value::Object(c)) => c.values_mut().collect(),
+        _ => vec![],
+    };
+    for sprite in sprites {
+        if let Some(Value::Array(parts)) = sprite.get_mut("parts") {
+            for part in parts {
+                rename_raw(part, "unk", "variation_num", |raw| Value::from(raw >> 16));
+            }
+        }
+    }
+
+    let animations = match data.get_mut("animations") {
+        Some(Value::Array(c)) => c.iter_mut().collect(),
+        Some(Value::Object(c)) => c.values_mut().collect(),
+        _ => vec![],
+    };
+    for anim in animations {
+        if let Some(Value::Array(steps)) = anim.get_mut("steps") {
+            for step in steps {
+                rename_raw(step, "unk0", "pos", |raw| {
+                    Value::from(vec![(raw >> 16) as u16 as i16, raw as u16 as i16])
+                });
+            }
+        }
+    }
+}
+
+/// Replaces the raw integer in field `old` with field `new`, calculated from the raw value
+fn rename_raw(object: &mut Value, old: &str, new: &str, f: impl Fn(u32) -> Value) {
+    let Some(object) = object.as_object_mut() else {
+        return;
+    };
+    let Some(raw) = object.get(old).and_then(Value::as_u64) else {
+        return;
+    };
+    let value = f(raw as u32);
+    object.remove(old);
+    object.insert(new.to_string(), value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{bxcad::BXCADWrapper, BRCAD, BXCAD};
+
+    const OLD_JSON: &str = include_str!("../../tests/fixtures/sample_1.0.0.json");
+
+    #[test]
+    fn upgrade_fixture() {
+        let mut value: Value = serde_json::from_str(OLD_JSON).unwrap();
+        assert_eq!(upgrade(&mut value).unwrap(), Version::new(1, 0, 0));
+        assert_eq!(value["flour_version"], env!("CARGO_PKG_VERSION"));
+
+        let wrapper: BXCADWrapper<BRCAD> = serde_json::from_value(value).unwrap();
+        let brcad = wrapper.to_bxcad().unwrap();
+        assert!(!brcad.has_variations());
+        assert_eq!(brcad.sprites[1].parts[0].variation_num(), 1);
+        let mut data = vec![];
+        brcad.to_binary(&mut data).unwrap();
+        assert_eq!(data, include_bytes!("../../tests/fixtures/sample.brcad"));
+    }
+
+    #[test]
+    fn old_files_need_upgrading() {
+        let wrapper: BXCADWrapper<BRCAD> = serde_json::from_str(OLD_JSON).unwrap();
+        assert!(matches!(
+            wrapper.to_bxcad(),
+            Err(Error::IncompatibleVersion(c)) if c == Version::new(1, 0, 0)
+        ));
+    }
+}
@@ -35,15 +35,132 @@ pub enum Error {
     /// Interpreting a BXCADWrapper from an incompatible version
     #[error(
         "This file was made with an incompatible flour version: {0}\n\
-        flour can read files made from version {} up to the current version",
-        crate::bxcad::OLDEST_SUPPORTED_VERSION
+        flour can read files made from version {} up to the current version, \
+        and upgrade files made from version {}",
+        crate::bxcad::OLDEST_SUPPORTED_VERSION,
+        crate::bxcad::upgrade::OLDEST_UPGRADABLE_VERSION
     )]
     IncompatibleVersion(semver::Version),
 
+    /// Interpreting a BXCADWrapper from a newer flour version
+    #[error(
+        "This file was made with a newer flour version: {0}\n\
+        Update flour to at least that version to read it"
+    )]
+    NewerVersion(semver::Version),
+
+    /// The flour version in a BXCADWrapper isn't valid SemVer
+    #[error("{0:?} is not a valid flour version")]
+    BadFlourVersion(String),
+
     /// File is not a BXCAD
     #[error("File given is not a known BXCAD file")]
     NotBXCAD,
 
+    /// An animation step refers to a sprite name that no sprite has
+    #[error("No sprite is named {0:?}")]
+    UnknownSpriteName(String),
+
+    /// An animation step refers to a sprite name that more than one sprite has
+    #[error("More than one sprite is named {0:?}")]
+    AmbiguousSpriteName(String),
+
+    /// An animation step refers to a sprite by name in a file that isn't indexized
+    #[error("Animation steps can only refer to sprites by name, like {0:?}, in indexized files (with \"indexize\": true)")]
+    SpriteNameNotIndexized(String),
+
+    /// Sprite index is past the end of the sprite list
+    #[error("There is no sprite number {0}")]
+    SpriteOutOfRange(u16),
+
+    /// Tried to remove a sprite that animation steps still use
+    #[error("Sprite number {0} is used by at least one animation")]
+    SpriteInUse(u16),
+
+    /// Sprite indexes don't fit in 16 bits anymore
+    #[error("Too many sprites, sprite indexes can't go over {}", u16::MAX)]
+    TooManySprites,
+
+    /// There's no animation with the given name
+    #[error("There is no animation named {0:?}")]
+    AnimationNotFound(String),
+
+    /// There's already an animation with the given name
+    #[error("There is already an animation named {0:?}")]
+    AnimationExists(String),
+
+    /// Tried to combine BXCADs of different types
+    #[error("Both files must be the same type of BXCAD")]
+    DifferentBXCADTypes,
+
+    /// A merge found values that were changed differently in both versions
+    #[error("Found {0} conflicts while merging")]
+    MergeConflicts(usize),
+
+    /// `flour fmt --check` found files that aren't in the canonical layout
+    #[error("{0} files are not formatted")]
+    UnformattedFiles(usize),
+
+    /// File is not a BPS patch
+    #[error("File given is not a BPS patch")]
+    NotBPS,
+
+    /// BPS patch is corrupted or otherwise invalid
+    #[error("Patch is corrupted or invalid")]
+    InvalidPatch,
+
+    /// BPS patch was made for a different file than the one it's being applied to
+    #[error("Patch was made for a different file")]
+    PatchSourceMismatch,
+
+    /// JSON file is malformed or doesn't match the expected format
+    #[error("Error in JSON file: {0}")]
+    BadJson(Box<crate::json::JsonError>),
+
+    /// JSON file has fields that don't exist in the format, in strict mode
+    #[error("Unknown fields in JSON file:\n{0}")]
+    UnknownFields(crate::json::UnknownFields),
+
+    /// Format name not known by flour
+    #[error("Unknown format {0:?}, expected json, yaml, toml or ron")]
+    UnknownFormat(String),
+
+    /// File in a format other than JSON is malformed or doesn't match the expected format
+    #[error("Error in {0} file: {1}")]
+    FormatError(crate::format::Format, String),
+
+    /// Directory given has no manifest file from `flour serialize --split`
+    #[error("Directory {0:?} has no flour manifest file")]
+    NoManifest(std::path::PathBuf),
+
+    /// A file with `$include` or `$ref` directives would be overwritten with them expanded
+    #[error("{0:?} has $include or $ref directives, which would be lost by overwriting it, give an output file instead")]
+    WouldLoseDirectives(std::path::PathBuf),
+
+    /// Error in one of the files of a directory from `flour serialize --split`
+    #[error("In {0:?}: {1}")]
+    InSplitFile(std::path::PathBuf, Box<Error>),
+
+    /// An `$include` or `$ref` directive is invalid or stands for a value that doesn't exist
+    #[error("Bad $include or $ref: {0}")]
+    BadInclude(String),
+
+    /// An `$include` or `$ref` directive ends up including itself
+    #[error("{0:?} includes itself")]
+    IncludeCycle(String),
+
+    /// Option `--labels` was given while converting several files
+    #[error("--labels can only be used when converting a single file")]
+    LabelsOnSeveralFiles,
+
+    /// Glob pattern is invalid
+    #[error("Invalid pattern {0:?}: {1}")]
+    BadPattern(String, String),
+
+    /// Some of the files being converted at once couldn't be converted
+    #[error("Failed to convert {0} files")]
+    FailedFiles(usize),
+
     /// File is not a flour JSON
     #[error("File given is not a flour JSON file")]
     NotFlour,
@@ -67,6 +184,18 @@ impl From<serde_json::Error> for Error {
     }
 }
 
+impl From<crate::json::JsonError> for Error {
+    fn from(err: crate::json::JsonError) -> Self {
+        Self::BadJson(Box::new(err))
+    }
+}
+
+impl From<crate::json::UnknownFields> for Error {
+    fn from(err: crate::json::UnknownFields) -> Self {
+        Self::UnknownFields(err)
+    }
+}
+
 impl From<semver::Error> for Error {
     fn from(err: semver::Error) -> Self {
         Self::SemverError(err)
@@ -0,0 +1,196 @@
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    fmt::{self, Display},
+    path::Path,
+    str::FromStr,
+};
+
+use crate::error::{Error, Result};
+
+/// A text format that a [BXCADWrapper](crate::bxcad::BXCADWrapper) can be written in
+///
+/// Formats other than JSON need the `formats` feature. They're written from the same data as
+/// the JSON, so they keep the `bxcad_type`, `flour_version` and `indexize` fields, but they
+/// can't keep comments. TOML has no null, so fields that would be null are left out instead.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Format {
+    #[default]
+    Json,
+    Yaml,
+    Toml,
+    Ron,
+}
+
+impl Format {
+    /// Figures out the format of a file from its extension
+    pub fn from_extension(path: &Path) -> Option<Self> {
+        path.extension()?.to_str()?.parse().ok()
+    }
+
+    /// The usual extension for files in this format
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::Yaml => "yaml",
+            Self::Toml => "toml",
+            Self::Ron => "ron",
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s.to_ascii_lowercase().as_str() {
+            "json" => Self::Json,
+            "yaml" | "yml" => Self::Yaml,
+            "toml" => Self::Toml,
+            "ron" => Self::Ron,
+            _ => Err(Error::UnknownFormat(s.to_string()))?,
+        })
+    }
+}
+
+impl Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Json => "JSON",
+            Self::Yaml => "YAML",
+            Self::Toml => "TOML",
+            Self::Ron => "RON",
+        })
+    }
+}
+
+/// Writes the data in the given format. JSON is pretty-printed
+pub fn to_string<T: Serialize>(data: &T, format: Format) -> Result<String> {
+    let value = serde_json::to_value(data)?;
+    Ok(match format {
+        Format::Json => serde_json::to_string_pretty(&value)?,
+        #[cfg(feature = "formats")]
+        Format::Yaml => serde_yaml::to_string(&value).map_err(|e| format_error(format, e))?,
+        #[cfg(feature = "formats")]
+        Format::Toml => {
+            let mut value = value;
+            remove_nulls(&mut value);
+            toml::to_string_pretty(&value).map_err(|e| format_error(format, e))?
+        }
+        #[cfg(feature = "formats")]
+        Format::Ron => ron::ser::to_string_pretty(&value, ron::ser::PrettyConfig::default())
+            .map_err(|e| format_error(format, e))?,
+        #[cfg(not(feature = "formats"))]
+        _ => Err(Error::NonImplementedFeature(format!(
+            "{} without the `formats` feature",
+            format
+        )))?,
+    })
+}
+
+/// Reads data written in the given format. JSON may have comments
+pub fn from_str<T: DeserializeOwned>(text: &str, format: Format) -> Result<T> {
+    // the other formats are written from a JSON value (see to_string), which RON doesn't write
+    // structs the same way as, so they're read back through one too
+    let value: serde_json::Value = match format {
+        Format::Json => return crate::json::from_str(text),
+        #[cfg(feature = "formats")]
+        Format::Yaml => serde_yaml::from_str(text).map_err(|e| format_error(format, e))?,
+        #[cfg(feature = "formats")]
+        Format::Toml => toml::from_str(text).map_err(|e| format_error(format, e))?,
+        #[cfg(feature = "formats")]
+        Format::Ron => ron::from_str(text).map_err(|e| format_error(format, e))?,
+        #[cfg(not(feature = "formats"))]
+        _ => Err(Error::NonImplementedFeature(format!(
+            "{} without the `formats` feature",
+            format
+        )))?,
+    };
+    Ok(serde_json::from_value(value)?)
+}
+
+#[cfg(feature = "formats")]
+fn format_error(format: Format, err: impl Display) -> Error {
+    Error::FormatError(format, err.to_string())
+}
+
+#[cfg(feature = "formats")]
+fn remove_nulls(value: &mut serde_json::Value) {
+    use serde_json::Value;
+    match value {
+        Value::Object(object) => {
+            object.retain(|_, c| !c.is_null());
+            object.values_mut().for_each(remove_nulls);
+        }
+        Value::Array(array) => array.iter_mut().for_each(remove_nulls),
+        _ => {}
+    }
+}
+
+#[cfg(all(test, feature = "formats"))]
+mod tests {
+    use super::*;
+    use crate::{bxcad::BXCADWrapper, BCCAD, BRCAD, BXCAD};
+    use std::io::Cursor;
+
+    fn round_trip<X: BXCAD + Serialize + DeserializeOwned>(data: &[u8], format: Format) -> String {
+        let bxcad = X::from_binary(&mut Cursor::new(data)).unwrap();
+        let text = to_string(&BXCADWrapper::from_bxcad(bxcad), format).unwrap();
+        let wrapper: BXCADWrapper<X> = from_str(&text, format).unwrap();
+        let mut written = vec![];
+        wrapper.to_bxcad().unwrap().to_binary(&mut written).unwrap();
+        assert_eq!(written, data, "{}", format);
+        text
+    }
+
+    fn bccad_without_timestamp() -> Vec<u8> {
+        let mut bccad = BCCAD::from_binary(&mut Cursor::new(include_bytes!(
+            "../tests/fixtures/sample.bccad"
+        )))
+        .unwrap();
+        bccad.timestamp = None;
+        let mut data = vec![];
+        bccad.to_binary(&mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn yaml_round_trip() {
+        round_trip::<BCCAD>(
+            include_bytes!("../tests/fixtures/sample.bccad"),
+            Format::Yaml,
+        );
+        round_trip::<BRCAD>(
+            include_bytes!("../tests/fixtures/sample.brcad"),
+            Format::Yaml,
+        );
+    }
+
+    #[test]
+    fn toml_round_trip() {
+        round_trip::<BCCAD>(
+            include_bytes!("../tests/fixtures/sample.bccad"),
+            Format::Toml,
+        );
+        round_trip::<BRCAD>(
+            include_bytes!("../tests/fixtures/sample.brcad"),
+            Format::Toml,
+        );
+
+        // TOML has no null, so the timestamp is left out instead
+        let text = round_trip::<BCCAD>(&bccad_without_timestamp(), Format::Toml);
+        assert!(!text.contains("timestamp"));
+    }
+
+    #[test]
+    fn ron_round_trip() {
+        round_trip::<BCCAD>(
+            include_bytes!("../tests/fixtures/sample.bccad"),
+            Format::Ron,
+        );
+        round_trip::<BRCAD>(
+            include_bytes!("../tests/fixtures/sample.brcad"),
+            Format::Ron,
+        );
+        round_trip::<BCCAD>(&bccad_without_timestamp(), Format::Ron);
+    }
+}
@@ -0,0 +1,427 @@
+use json_comments::{CommentSettings, StripComments};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use std::{
+    fmt::{self, Display},
+    io::Read,
+};
+
+use crate::error::Result;
+
+/// Editing JSON without losing its comments
+pub mod document;
+/// `$include` and `$ref` directives, to reuse values from the same file or from others
+pub mod include;
+
+/// Maximum amount of characters of the offending line shown in a [JsonError]
+const SNIPPET_WIDTH: usize = 80;
+/// Minimum similarity (Jaro-Winkler) for a known field to be suggested for an unknown one
+const SUGGESTION_THRESHOLD: f64 = 0.8;
+
+/// Deserialize a value from JSON that may have comments, with a [JsonError] pointing to the
+/// problem if it fails
+pub fn from_str<T: DeserializeOwned>(json: &str) -> Result<T> {
+    let stripped = strip_comments(json)?;
+    let mut de = serde_json::Deserializer::from_str(&stripped);
+    let value = serde_path_to_error::deserialize(&mut de)
+        .map_err(|err| JsonError::new(err.inner(), err.path().to_string(), json))?;
+    de.end()
+        .map_err(|err| JsonError::new(&err, String::new(), json))?;
+    Ok(value)
+}
+
+/// Like [from_str], but fails with [UnknownFields] if the JSON has any fields that would
+/// otherwise be ignored
+///
+/// `T` has to be serializable so that the fields it does know can be suggested as corrections.
+pub fn from_str_strict<T: DeserializeOwned + Serialize>(json: &str) -> Result<T> {
+    let stripped = strip_comments(json)?;
+    let mut unknown = vec![];
+    let mut de = serde_json::Deserializer::from_str(&stripped);
+    let mut callback = |path: serde_ignored::Path| unknown.push(segments(&path));
+    let result: std::result::Result<T, _> =
+        serde_path_to_error::deserialize(serde_ignored::Deserializer::new(&mut de, &mut callback));
+
+    let value = match result {
+        Ok(c) => c,
+        // a misspelled field usually makes a required one go missing, so the unknown fields
+        // explain the problem better than the error does
+        Err(err) if !unknown.is_empty() => {
+            let missing = err
+                .inner()
+                .to_string()
+                .strip_prefix("missing field `")
+                .and_then(|c| c.split('`').next())
+                .map(|c| (err.path().to_string(), c.to_string()));
+            let fields = unknown
+                .into_iter()
+                .map(|path| UnknownField::new(path, None, missing.as_ref()))
+                .collect();
+            return Err(UnknownFields(fields).into());
+        }
+        Err(err) => Err(JsonError::new(err.inner(), err.path().to_string(), json))?,
+    };
+    de.end()
+        .map_err(|err| JsonError::new(&err, String::new(), json))?;
+
+    if !unknown.is_empty() {
+        let known = serde_json::to_value(&value)?;
+        let fields = unknown
+            .into_iter()
+            .map(|path| UnknownField::new(path, Some(&known), None))
+            .collect();
+        Err(UnknownFields(fields))?
+    }
+    Ok(value)
+}
+
+/// Replaces all the [Color](crate::Color) objects in a JSON value with `"#rrggbb"` strings,
+/// which flour also reads
+pub fn colors_to_hex(value: &mut Value) {
+    match value {
+        Value::Object(object) => {
+            let color: Option<crate::Color> = if object.len() == 3 {
+                serde_json::from_value(Value::Object(object.clone())).ok()
+            } else {
+                None
+            };
+            match color {
+                Some(color) => *value = Value::from(color.to_hex()),
+                None => object.values_mut().for_each(colors_to_hex),
+            }
+        }
+        Value::Array(array) => array.iter_mut().for_each(colors_to_hex),
+        _ => {}
+    }
+}
+
+fn strip_comments(json: &str) -> Result<String> {
+    // comments get replaced with whitespace, so positions still match the original
+    let mut stripped = String::new();
+    StripComments::with_settings(CommentSettings::c_style(), json.as_bytes())
+        .read_to_string(&mut stripped)?;
+    Ok(stripped)
+}
+
+/// Part of the path to a JSON value
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+fn segments(path: &serde_ignored::Path) -> Vec<Segment> {
+    use serde_ignored::Path;
+    match path {
+        Path::Root => vec![],
+        Path::Seq { parent, index } => {
+            let mut c = segments(parent);
+            c.push(Segment::Index(*index));
+            c
+        }
+        Path::Map { parent, key } => {
+            let mut c = segments(parent);
+            c.push(Segment::Key(key.clone()));
+            c
+        }
+        Path::Some { parent }
+        | Path::NewtypeStruct { parent }
+        | Path::NewtypeVariant { parent } => segments(parent),
+    }
+}
+
+/// Error found while reading a JSON file, with the position of the problem
+#[derive(Debug)]
+pub struct JsonError {
+    /// Description of the problem
+    pub message: String,
+    /// Path of the field where the problem was found, like `data.sprites[2].parts[0].x`
+    ///
+    /// Empty if the JSON couldn't be parsed at all or the problem is at the root
+    pub path: String,
+    /// Where the problem is in the text, unless the text isn't what the user wrote (see
+    /// [JsonError::without_location])
+    pub location: Option<Location>,
+}
+
+/// Position of a [JsonError] in the JSON text
+#[derive(Debug)]
+pub struct Location {
+    /// Line of the problem, starting at 1
+    pub line: usize,
+    /// Column of the problem, starting at 1. Like in serde_json, this counts bytes, not characters
+    pub column: usize,
+    /// The contents of the offending line
+    pub snippet: String,
+}
+
+impl JsonError {
+    fn new(err: &serde_json::Error, path: String, json: &str) -> Self {
+        let (line, column) = (err.line(), err.column());
+        // serde_json adds the position to the message on its own
+        let message = err.to_string();
+        let message = message
+            .strip_suffix(&format!(" at line {} column {}", line, column))
+            .unwrap_or(&message)
+            .to_string();
+        let snippet = json.lines().nth(line.saturating_sub(1)).unwrap_or_default();
+        let path = if path == "." { String::new() } else { path };
+
+        Self {
+            message,
+            path,
+            location: Some(Location {
+                line,
+                column,
+                snippet: snippet.to_string(),
+            }),
+        }
+    }
+
+    /// Drops the location of the error, for JSON that was written by flour from some other
+    /// source (like YAML, or files joined together), since it wouldn't point to anything the
+    /// user can find. The path of the field is still there
+    pub fn without_location(self) -> Self {
+        Self {
+            location: None,
+            ..self
+        }
+    }
+}
+
+impl Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        if !self.path.is_empty() {
+            write!(f, " in {}", self.path)?;
+        }
+        let Some(location) = &self.location else {
+            return Ok(());
+        };
+        writeln!(f, " (line {}, column {})", location.line, location.column)?;
+
+        // serde_json counts bytes, so find the character the column is in
+        let mut byte = location
+            .column
+            .saturating_sub(1)
+            .min(location.snippet.len());
+        while !location.snippet.is_char_boundary(byte) {
+            byte -= 1;
+        }
+        let column = location.snippet[..byte].chars().count();
+
+        // only show the part of the line around the problem if it's too long
+        let chars: Vec<char> = location.snippet.chars().collect();
+        let start = column.saturating_sub(SNIPPET_WIDTH / 2);
+        let end = (start + SNIPPET_WIDTH).min(chars.len());
+        let snippet: String = chars[start..end].iter().collect();
+
+        let gutter = location.line.to_string();
+        writeln!(f, "{} | {}", gutter, snippet.trim_end())?;
+        write!(
+            f,
+            "{} | {}^",
+            " ".repeat(gutter.len()),
+            " ".repeat(column - start)
+        )
+    }
+}
+
+/// A field in a JSON file that doesn't exist in the format
+#[derive(Debug)]
+pub struct UnknownField {
+    /// Path of the field, like `data.sprites[2].parts[0].scalex`
+    pub path: String,
+    /// Most similar field that does exist in the same place, if any is similar enough
+    pub suggestion: Option<String>,
+}
+
+impl UnknownField {
+    /// Suggestions are taken from the fields at the same place in `known`, and from `missing`
+    /// (path of the parent and name of a field that failed to be found) if the parent matches
+    fn new(path: Vec<Segment>, known: Option<&Value>, missing: Option<&(String, String)>) -> Self {
+        let mut parent = known;
+        let mut parent_path = String::new();
+        let mut path_str = String::new();
+        let mut name = String::new();
+        for (i, segment) in path.iter().enumerate() {
+            let is_last = i == path.len() - 1;
+            if is_last {
+                parent_path = path_str.clone();
+            }
+            match segment {
+                Segment::Key(key) => {
+                    if !path_str.is_empty() {
+                        path_str.push('.');
+                    }
+                    path_str.push_str(key);
+                    if is_last {
+                        name = key.clone();
+                    } else {
+                        parent = parent.and_then(|c| c.get(key));
+                    }
+                }
+                Segment::Index(index) => {
+                    path_str.push_str(&format!("[{}]", index));
+                    if !is_last {
+                        parent = parent.and_then(|c| c.get(index));
+                    }
+                }
+            }
+        }
+
+        let missing = missing
+            .filter(|(path, _)| path.trim_start_matches('.') == parent_path)
+            .map(|(_, field)| field);
+        let suggestion = parent
+            .and_then(Value::as_object)
+            .into_iter()
+            .flat_map(|c| c.keys())
+            .chain(missing)
+            .map(|c| (strsim::jaro_winkler(&name, c), c))
+            .filter(|(similarity, _)| *similarity >= SUGGESTION_THRESHOLD)
+            .max_by(|a, b| a.0.total_cmp(&b.0))
+            .map(|(_, c)| c.clone());
+
+        Self {
+            path: path_str,
+            suggestion,
+        }
+    }
+}
+
+impl Display for UnknownField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown field {}", self.path)?;
+        if let Some(suggestion) = &self.suggestion {
+            write!(f, " (did you mean `{}`?)", suggestion)?;
+        }
+        Ok(())
+    }
+}
+
+/// All the unknown fields found in a JSON file by [from_str_strict]
+#[derive(Debug)]
+pub struct UnknownFields(pub Vec<UnknownField>);
+
+impl Display for UnknownFields {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, field) in self.0.iter().enumerate() {
+            if i != 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", field)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+
+    #[derive(serde::Deserialize, Serialize, Debug)]
+    struct Point {
+        x: u8,
+        y: u8,
+    }
+
+    #[derive(serde::Deserialize, Serialize, Debug)]
+    struct Sprite {
+        #[serde(default)]
+        name: Option<String>,
+        parts: Vec<Part>,
+    }
+
+    #[derive(serde::Deserialize, Serialize, Debug)]
+    struct Part {
+        scale_x: f32,
+        scale_y: f32,
+        #[serde(default)]
+        flip_x: bool,
+    }
+
+    fn unknown_fields(json: &str) -> Vec<(String, Option<String>)> {
+        match from_str_strict::<Sprite>(json).unwrap_err() {
+            Error::UnknownFields(c) => c.0.into_iter().map(|c| (c.path, c.suggestion)).collect(),
+            c => panic!("expected unknown fields, got {:?}", c),
+        }
+    }
+
+    fn json_error(err: Error) -> JsonError {
+        match err {
+            Error::BadJson(c) => *c,
+            c => panic!("expected a JSON error, got {:?}", c),
+        }
+    }
+
+    #[test]
+    fn error_points_to_the_problem() {
+        let json = "{\n  // comment\n  \"x\": 1,\n  \"y\": 300\n}";
+        let err = json_error(from_str::<Point>(json).unwrap_err());
+        assert_eq!(err.path, "y");
+        let location = err.location.unwrap();
+        assert_eq!((location.line, location.column), (4, 10));
+        assert_eq!(location.snippet, "  \"y\": 300");
+    }
+
+    #[test]
+    fn caret_counts_characters() {
+        let json = "{\"x\": 1, \"ñame\": \"ñáé\", \"y\": 300}";
+        let err = json_error(from_str::<Point>(json).unwrap_err());
+        assert_eq!(err.location.as_ref().unwrap().column, 36);
+        let shown = err.to_string();
+        let mut lines = shown.lines().skip(1);
+        let snippet = lines.next().unwrap();
+        let caret = lines.next().unwrap();
+        assert_eq!(snippet, format!("1 | {}", json));
+        let caret = caret.chars().count() - 1;
+        assert_eq!(snippet.chars().nth(caret), Some('0'));
+        assert_eq!(snippet.chars().nth(caret + 1), Some('}'));
+    }
+
+    #[test]
+    fn error_without_location_keeps_the_path() {
+        let err = json_error(from_str::<Point>("{\"x\": 1, \"y\": -1}").unwrap_err());
+        let err = err.without_location();
+        assert!(err.location.is_none());
+        assert_eq!(err.to_string(), format!("{} in y", err.message));
+    }
+
+    #[test]
+    fn strict_suggests_misspelled_optional_field() {
+        let fields = unknown_fields("{\"nmae\": \"a\", \"parts\": []}");
+        assert_eq!(fields, [("nmae".to_string(), Some("name".to_string()))]);
+        let json = "{\"parts\": []}";
+        assert!(from_str_strict::<Sprite>(json).is_ok());
+    }
+
+    #[test]
+    fn strict_suggests_misspelled_required_field() {
+        let fields = unknown_fields("{\"prats\": []}");
+        assert_eq!(fields, [("prats".to_string(), Some("parts".to_string()))]);
+    }
+
+    #[test]
+    fn strict_finds_fields_inside_arrays() {
+        let json = r#"{"parts": [
+            {"scale_x": 1, "scale_y": 1},
+            {"scale_x": 1, "scaley": 1}
+        ]}"#;
+        let fields = unknown_fields(json);
+        assert_eq!(
+            fields,
+            [("parts[1].scaley".to_string(), Some("scale_y".to_string()))]
+        );
+
+        let json = r#"{"parts": [
+            {"scale_x": 1, "scale_y": 1},
+            {"scale_x": 1, "scale_y": 1, "flipx": true}
+        ]}"#;
+        let fields = unknown_fields(json);
+        assert_eq!(
+            fields,
+            [("parts[1].flipx".to_string(), Some("flip_x".to_string()))]
+        );
+    }
+}
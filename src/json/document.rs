@@ -0,0 +1,614 @@
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    fmt::{self, Display, Write},
+};
+
+use crate::{bxcad::diff::named_elements, error::Result};
+
+/// A JSON document that keeps the comments it was written with, so that it can be changed and
+/// written back without losing them
+///
+/// Comments are attached to the value they're next to, and are written back next to the same
+/// value even if it moved around. Elements of arrays are told apart by their index, except for
+/// animations with unique names, which are told apart by name, so when sprites are removed the
+/// comments have to be moved along with them (see [Document::remove_sprites]). Comments
+/// attached to values that no longer exist are dropped.
+///
+/// When written, the document is formatted the same way as [serde_json::to_string_pretty].
+#[derive(Clone, Debug, Default)]
+pub struct Document {
+    value: Value,
+    comments: HashMap<Vec<Key>, Comments>,
+    /// Comments after the root value
+    footer: Vec<String>,
+}
+
+/// Part of the path to a value in a [Document]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum Key {
+    Field(String),
+    Index(usize),
+    Name(String),
+}
+
+/// Comments attached to a single value
+#[derive(Clone, Debug, Default)]
+struct Comments {
+    /// In the lines before the value
+    before: Vec<String>,
+    /// In the same line as the end of the value
+    inline: Vec<String>,
+    /// Before the closing bracket of an array or object
+    closing: Vec<String>,
+}
+
+impl Document {
+    /// Parse a JSON document with comments
+    pub fn parse(json: &str) -> Result<Self> {
+        // get any syntax errors out of the way, with proper diagnostics
+        let value: Value = super::from_str(json)?;
+        let mut document = Self {
+            value,
+            ..Default::default()
+        };
+        let comments = Parser::new(json).comments();
+        for (path, position, text) in comments.comments {
+            let path = document.identity(&path);
+            let entry = document.comments.entry(path).or_default();
+            match position {
+                Position::Before => entry.before.push(text),
+                Position::Inline => entry.inline.push(text),
+                Position::Closing => entry.closing.push(text),
+            }
+        }
+        document.footer = comments.footer;
+        Ok(document)
+    }
+
+    /// Creates a document from a value, without any comments
+    pub fn from_value(value: Value) -> Self {
+        Self {
+            value,
+            ..Default::default()
+        }
+    }
+
+    /// The document's contents
+    pub fn value(&self) -> &Value {
+        &self.value
+    }
+
+    /// Replaces the document's contents, keeping the comments
+    pub fn set_value(&mut self, value: Value) {
+        self.value = value;
+    }
+
+    /// Interprets the document's contents as the given type
+    pub fn deserialize<T: DeserializeOwned>(&self) -> Result<T> {
+        Ok(T::deserialize(&self.value)?)
+    }
+
+    /// Replaces the document's contents with the given data, keeping the comments
+    pub fn update<T: Serialize>(&mut self, data: &T) -> Result<()> {
+        self.value = serde_json::to_value(data)?;
+        Ok(())
+    }
+
+    /// Whether the document has any comments
+    pub fn has_comments(&self) -> bool {
+        !self.comments.is_empty() || !self.footer.is_empty()
+    }
+
+    /// Moves the comments of the sprites of a [BXCADWrapper](crate::bxcad::BXCADWrapper) to
+    /// match the sprites' new indexes after the sprites at the given indexes were removed, as
+    /// in [Editable::remove_unused_sprites](crate::bxcad::edit::Editable::remove_unused_sprites)
+    ///
+    /// The comments of the removed sprites are dropped.
+    pub fn remove_sprites(&mut self, removed: &[u16]) {
+        let new_index = |index: usize| -> Option<usize> {
+            if removed.iter().any(|c| *c as usize == index) {
+                return None;
+            }
+            Some(index - removed.iter().filter(|c| (**c as usize) < index).count())
+        };
+        let comments = std::mem::take(&mut self.comments);
+        for (mut path, comments) in comments {
+            if path.len() > 2 && is_sprites(&path[..2]) {
+                // indexized sprites are keyed by their index too
+                let key = match &path[2] {
+                    Key::Index(c) => new_index(*c).map(Key::Index),
+                    Key::Field(c) => match c.parse() {
+                        Ok(c) => new_index(c).map(|c| Key::Field(c.to_string())),
+                        Err(_) => Some(path[2].clone()),
+                    },
+                    c => Some(c.clone()),
+                };
+                let Some(key) = key else {
+                    continue;
+                };
+                path[2] = key;
+            }
+            self.comments.insert(path, comments);
+        }
+    }
+
+    /// Turns a path with array indexes into one that uses names where needed
+    fn identity(&self, path: &[Key]) -> Vec<Key> {
+        let mut out = vec![];
+        let mut value = Some(&self.value);
+        for key in path {
+            let (identity, next) = match (key, value) {
+                (Key::Index(index), Some(Value::Array(array))) if *index < array.len() => (
+                    element_keys(&out, array).swap_remove(*index),
+                    array.get(*index),
+                ),
+                (Key::Field(field), Some(c)) => (field_key(&out, field), c.get(field)),
+                _ => (key.clone(), None),
+            };
+            out.push(identity);
+            value = next;
+        }
+        out
+    }
+
+    /// Writes the document in flour's canonical layout, keeping the comments
+    ///
+    /// This is like [serde_json::to_string_pretty], except:
+    /// - [PosInTexture](crate::bxcad::PosInTexture), [Color](crate::Color) and
+    ///   [StereoDepth](crate::bxcad::bccad::StereoDepth) objects, and arrays of numbers,
+    ///   are written in a single line
+    /// - Indexized sprites are sorted by their index
+    pub fn to_canonical_string(&self) -> String {
+        let mut out = String::new();
+        // writing to a String can't fail
+        let _ = self.write(&mut out, true);
+        out
+    }
+
+    fn write(&self, f: &mut String, canonical: bool) -> fmt::Result {
+        if let Some(comments) = self.comments.get(&vec![]) {
+            for comment in &comments.before {
+                writeln!(f, "{}", comment)?;
+            }
+        }
+        self.write_value(f, &self.value, &mut vec![], 0, canonical)?;
+        self.write_after(f, &[], true)?;
+        for comment in &self.footer {
+            write!(f, "\n{}", comment)?;
+        }
+        Ok(())
+    }
+
+    fn write_value(
+        &self,
+        f: &mut String,
+        value: &Value,
+        path: &mut Vec<Key>,
+        indent: usize,
+        canonical: bool,
+    ) -> fmt::Result {
+        let comments = self.comments.get(path.as_slice());
+        let closing = comments.map(|c| c.closing.as_slice()).unwrap_or_default();
+        match value {
+            _ if canonical && is_single_line(value) && !self.has_comments_in(path) => {
+                write_single_line(f, value)?
+            }
+            Value::Array(array) if !array.is_empty() || !closing.is_empty() => {
+                f.push('[');
+                let keys = element_keys(path, array);
+                for (i, (element, key)) in array.iter().zip(keys).enumerate() {
+                    path.push(key);
+                    self.write_before(f, path, indent + 1)?;
+                    self.write_value(f, element, path, indent + 1, canonical)?;
+                    self.write_after(f, path, i + 1 == array.len())?;
+                    path.pop();
+                }
+                write_closing(f, closing, indent)?;
+                f.push(']');
+            }
+            Value::Object(object) if !object.is_empty() || !closing.is_empty() => {
+                f.push('{');
+                let mut fields: Vec<_> = object.iter().collect();
+                if canonical && is_sprites(path) {
+                    // indexized sprites, which get sorted unless something else is there
+                    let indexes: Option<Vec<usize>> =
+                        fields.iter().map(|(key, _)| key.parse().ok()).collect();
+                    if let Some(indexes) = indexes {
+                        let mut sorted: Vec<_> = indexes.into_iter().zip(fields).collect();
+                        sorted.sort_by_key(|(index, _)| *index);
+                        fields = sorted.into_iter().map(|(_, c)| c).collect();
+                    }
+                }
+                for (i, (key, element)) in fields.into_iter().enumerate() {
+                    path.push(field_key(path, key));
+                    self.write_before(f, path, indent + 1)?;
+                    write!(f, "{}: ", Value::from(key.as_str()))?;
+                    self.write_value(f, element, path, indent + 1, canonical)?;
+                    self.write_after(f, path, i + 1 == object.len())?;
+                    path.pop();
+                }
+                write_closing(f, closing, indent)?;
+                f.push('}');
+            }
+            Value::Array(_) => f.push_str("[]"),
+            Value::Object(_) => f.push_str("{}"),
+            c => write!(f, "{}", c)?,
+        }
+        Ok(())
+    }
+
+    /// Whether there's any comments for this path or anything inside it
+    fn has_comments_in(&self, path: &[Key]) -> bool {
+        self.comments.keys().any(|c| c.starts_with(path))
+    }
+
+    /// Starts the line for a value, with the comments before it
+    fn write_before(&self, f: &mut String, path: &[Key], indent: usize) -> fmt::Result {
+        f.push('\n');
+        if let Some(comments) = self.comments.get(path) {
+            for comment in &comments.before {
+                writeln!(f, "{}{}", "  ".repeat(indent), comment)?;
+            }
+        }
+        write!(f, "{}", "  ".repeat(indent))
+    }
+
+    /// Ends the line for a value, with the comments after it
+    fn write_after(&self, f: &mut String, path: &[Key], last: bool) -> fmt::Result {
+        if !last {
+            f.push(',');
+        }
+        if let Some(comments) = self.comments.get(path) {
+            for comment in &comments.inline {
+                write!(f, " {}", comment)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Display for Document {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut out = String::new();
+        self.write(&mut out, false)?;
+        f.write_str(&out)
+    }
+}
+
+/// Fields of the objects that are written in a single line in the canonical layout
+const SINGLE_LINE_OBJECTS: &[&[&str]] = &[
+    &["x", "y", "width", "height"],
+    &["red", "blue", "green"],
+    &["top_left", "bottom_left", "top_right", "bottom_right"],
+];
+
+/// Whether the value is written in a single line in the canonical layout
+fn is_single_line(value: &Value) -> bool {
+    match value {
+        Value::Array(array) => !array.is_empty() && array.iter().all(Value::is_number),
+        Value::Object(object) => SINGLE_LINE_OBJECTS.iter().any(|fields| {
+            object.len() == fields.len()
+                && fields
+                    .iter()
+                    .all(|c| object.get(*c).is_some_and(Value::is_number))
+        }),
+        _ => false,
+    }
+}
+
+fn write_single_line(f: &mut String, value: &Value) -> fmt::Result {
+    match value {
+        Value::Array(array) => {
+            f.push('[');
+            for (i, element) in array.iter().enumerate() {
+                if i != 0 {
+                    f.push_str(", ");
+                }
+                write!(f, "{}", element)?;
+            }
+            f.push(']');
+        }
+        Value::Object(object) => {
+            f.push('{');
+            for (i, (key, element)) in object.iter().enumerate() {
+                if i != 0 {
+                    f.push_str(", ");
+                }
+                write!(f, "{}: {}", Value::from(key.as_str()), element)?;
+            }
+            f.push('}');
+        }
+        c => write!(f, "{}", c)?,
+    }
+    Ok(())
+}
+
+/// Whether the path is the one for the sprites inside a [BXCADWrapper](crate::bxcad::BXCADWrapper)
+fn is_sprites(path: &[Key]) -> bool {
+    matches!(path, [Key::Field(a), Key::Field(b)] if a == "data" && b == "sprites")
+}
+
+/// Whether the path is the one for the animations inside a [BXCADWrapper](crate::bxcad::BXCADWrapper)
+fn is_animations(path: &[Key]) -> bool {
+    matches!(path, [Key::Field(a), Key::Field(b)] if a == "data" && b == "animations")
+}
+
+/// Keys for the elements of an array: names for animations if they're all unique, indexes
+/// for anything else
+fn element_keys(array_path: &[Key], array: &[Value]) -> Vec<Key> {
+    if is_animations(array_path) {
+        if let Some(named) = named_elements("animations", array) {
+            return named
+                .into_iter()
+                .map(|(name, _)| Key::Name(name.to_string()))
+                .collect();
+        }
+    }
+    (0..array.len()).map(Key::Index).collect()
+}
+
+/// Key for a field of an object, which is a name for animations stored as a map, so that they
+/// match the same animations stored as a list
+fn field_key(object_path: &[Key], field: &str) -> Key {
+    if is_animations(object_path) {
+        Key::Name(field.to_string())
+    } else {
+        Key::Field(field.to_string())
+    }
+}
+
+fn write_closing(f: &mut String, closing: &[String], indent: usize) -> fmt::Result {
+    for comment in closing {
+        write!(f, "\n{}{}", "  ".repeat(indent + 1), comment)?;
+    }
+    write!(f, "\n{}", "  ".repeat(indent))
+}
+
+/// Where a comment is, relative to the value it's attached to
+enum Position {
+    Before,
+    Inline,
+    Closing,
+}
+
+/// Comments found by the [Parser], with the paths they're attached to
+struct ParsedComments {
+    comments: Vec<(Vec<Key>, Position, String)>,
+    footer: Vec<String>,
+}
+
+/// An array or object the [Parser] is inside of
+struct Frame {
+    path: Vec<Key>,
+    is_object: bool,
+    index: usize,
+    key: String,
+    expecting_key: bool,
+}
+
+/// Goes through a JSON document that's known to be valid, finding where its comments are
+struct Parser<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+    line: usize,
+    stack: Vec<Frame>,
+    /// Comments waiting for the value they come before
+    pending: Vec<String>,
+    /// Path and line of the last value that ended
+    last_end: Option<(Vec<Key>, usize)>,
+    out: ParsedComments,
+}
+
+impl<'a> Parser<'a> {
+    fn new(json: &'a str) -> Self {
+        Self {
+            chars: json.chars().peekable(),
+            line: 0,
+            stack: vec![],
+            pending: vec![],
+            last_end: None,
+            out: ParsedComments {
+                comments: vec![],
+                footer: vec![],
+            },
+        }
+    }
+
+    fn comments(mut self) -> ParsedComments {
+        while let Some(c) = self.chars.next() {
+            match c {
+                '\n' => self.line += 1,
+                '/' => self.comment(),
+                '{' | '[' => {
+                    let path = self.value_start();
+                    self.last_end = None;
+                    self.stack.push(Frame {
+                        path,
+                        is_object: c == '{',
+                        index: 0,
+                        key: String::new(),
+                        expecting_key: true,
+                    });
+                }
+                '}' | ']' => {
+                    let Some(frame) = self.stack.pop() else {
+                        break;
+                    };
+                    for text in self.pending.drain(..) {
+                        self.out
+                            .comments
+                            .push((frame.path.clone(), Position::Closing, text));
+                    }
+                    self.last_end = Some((frame.path, self.line));
+                }
+                '"' => {
+                    let string = self.string();
+                    match self.stack.last_mut() {
+                        Some(frame) if frame.is_object && frame.expecting_key => {
+                            frame.key = string;
+                            frame.expecting_key = false;
+                            self.value_start();
+                        }
+                        _ => self.scalar(),
+                    }
+                }
+                ',' => {
+                    if let Some(frame) = self.stack.last_mut() {
+                        frame.index += 1;
+                        frame.expecting_key = true;
+                    }
+                }
+                c if c.is_whitespace() || c == ':' => {}
+                _ => {
+                    while self
+                        .chars
+                        .next_if(|c| !c.is_whitespace() && !",]}/".contains(*c))
+                        .is_some()
+                    {}
+                    self.scalar();
+                }
+            }
+        }
+        self.out.footer = self.pending;
+        self.out
+    }
+
+    /// Path of the value that's starting, which gets the pending comments. Inside objects,
+    /// values start at their key
+    fn value_start(&mut self) -> Vec<Key> {
+        let path = match self.stack.last() {
+            None => vec![],
+            Some(frame) => {
+                let mut path = frame.path.clone();
+                path.push(if frame.is_object {
+                    Key::Field(frame.key.clone())
+                } else {
+                    Key::Index(frame.index)
+                });
+                path
+            }
+        };
+        for text in self.pending.drain(..) {
+            self.out
+                .comments
+                .push((path.clone(), Position::Before, text));
+        }
+        path
+    }
+
+    fn scalar(&mut self) {
+        let path = match self.stack.last() {
+            Some(frame) if frame.is_object => {
+                let mut path = frame.path.clone();
+                path.push(Key::Field(frame.key.clone()));
+                path
+            }
+            _ => self.value_start(),
+        };
+        self.last_end = Some((path, self.line));
+    }
+
+    fn string(&mut self) -> String {
+        let mut raw = String::from('"');
+        while let Some(c) = self.chars.next() {
+            raw.push(c);
+            match c {
+                '\\' => raw.extend(self.chars.next()),
+                '"' => break,
+                _ => {}
+            }
+        }
+        serde_json::from_str(&raw).unwrap_or_default()
+    }
+
+    fn comment(&mut self) {
+        let start_line = self.line;
+        let mut text = String::from('/');
+        match self.chars.next() {
+            Some('/') => {
+                text.push('/');
+                while let Some(c) = self.chars.next_if(|c| *c != '\n') {
+                    text.push(c);
+                }
+                text = text.trim_end().to_string();
+            }
+            Some('*') => {
+                text.push('*');
+                for c in self.chars.by_ref() {
+                    text.push(c);
+                    if c == '\n' {
+                        self.line += 1;
+                    }
+                    if text.ends_with("*/") && text.len() > 3 {
+                        break;
+                    }
+                }
+            }
+            _ => return,
+        }
+
+        match &self.last_end {
+            Some((path, line)) if *line == start_line && self.pending.is_empty() => self
+                .out
+                .comments
+                .push((path.clone(), Position::Inline, text)),
+            _ => self.pending.push(text),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bxcad::{edit::Editable, BXCADWrapper},
+        BCCAD,
+    };
+
+    #[test]
+    fn gc_keeps_comments_with_their_sprites() {
+        let json = format!(
+            r#"{{
+  "bxcad_type": "BCCAD",
+  "flour_version": "{}",
+  "data": {{
+    "timestamp": null,
+    "texture_width": 64,
+    "texture_height": 64,
+    "sprites": [
+      // unused
+      {{"name": "a", "parts": []}},
+      {{"name": "b", "parts": []}}, // used
+      // also unused
+      {{"name": "c", "parts": []}},
+      // used too
+      {{"name": "d", "parts": []}}
+    ],
+    "animations": [
+      {{"name": "anim", "interpolation": 0, "steps": [{{"sprite": 1, "duration": 1}}, {{"sprite": 3, "duration": 1}}]}}
+    ]
+  }}
+}}"#,
+            env!("CARGO_PKG_VERSION")
+        );
+        let mut document = Document::parse(&json).unwrap();
+        let wrapper: BXCADWrapper<BCCAD> = document.deserialize().unwrap();
+        let mut bccad = wrapper.to_bxcad().unwrap();
+
+        let removed: Vec<_> = bccad
+            .remove_unused_sprites()
+            .into_iter()
+            .map(|(i, _)| i)
+            .collect();
+        document.update(&BXCADWrapper::from_bxcad(bccad)).unwrap();
+        document.remove_sprites(&removed);
+
+        let out = document.to_string();
+        assert!(!out.contains("unused"));
+        let line = |text: &str| out.lines().position(|c| c.contains(text)).unwrap();
+        // the inline comment ends the sprite after "b", the other one starts the one with "d"
+        assert_eq!(line("// used"), line("\"b\"") + 2);
+        assert_eq!(line("// used too") + 2, line("\"d\""));
+    }
+}
@@ -0,0 +1,265 @@
+use serde_json::{Map, Value};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use crate::{
+    error::{Error, Result},
+    format::{self, Format},
+};
+
+/// Key of the directive that is replaced with a value from another file
+pub const INCLUDE: &str = "$include";
+/// Key of the directive that is replaced with another value from the same file, or from
+/// another file
+pub const REF: &str = "$ref";
+
+/// Maximum nesting of directives, in case a cycle goes unnoticed
+const MAX_DEPTH: usize = 64;
+
+/// Replaces all the `$include` and `$ref` directives in a value read from the file at `path`,
+/// returning whether there were any
+///
+/// A directive is an object like `{"$include": "shared/ui.json#/sprites/0"}`: the path of a
+/// file, relative to the file the directive is in, then `#` and a [JSON pointer] to the value
+/// to use from it. Without a pointer, the whole file is used, and without a path, the value is
+/// taken from the same file, so `{"$ref": "#/data/sprites/0"}` stands for the first sprite of
+/// the file. Both keys work the same way, `$ref` just reads better for the latter.
+///
+/// Any other fields in a directive replace those of the value it stands for, so
+/// `{"$ref": "#/data/sprites/0/parts/0", "pos_x": 20}` is the same sprite part but somewhere
+/// else. A directive that stands for an array, inside of another array, is replaced by all of
+/// its elements, so a set of sprite parts can be shared as a whole.
+///
+/// Files in other formats than JSON can be included too, judging by their extension.
+///
+/// [JSON pointer]: https://datatracker.ietf.org/doc/html/rfc6901
+pub fn expand(value: &mut Value, path: &Path) -> Result<bool> {
+    if !has_directives(value) {
+        return Ok(false);
+    }
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let root = Rc::new(value.clone());
+    let mut expander = Expander::default();
+    expander.files.insert(path.clone(), root.clone());
+    expander.expand(value, &Source { path, root })?;
+    Ok(true)
+}
+
+/// Whether the value has any `$include` or `$ref` directives
+pub fn has_directives(value: &Value) -> bool {
+    match value {
+        Value::Object(object) => {
+            object.contains_key(INCLUDE)
+                || object.contains_key(REF)
+                || object.values().any(has_directives)
+        }
+        Value::Array(array) => array.iter().any(has_directives),
+        _ => false,
+    }
+}
+
+/// A file directives are resolved against
+struct Source {
+    path: PathBuf,
+    /// Contents of the file, before expanding it
+    root: Rc<Value>,
+}
+
+#[derive(Default)]
+struct Expander {
+    /// Files that have already been read
+    files: HashMap<PathBuf, Rc<Value>>,
+    /// Directives being expanded, to catch them including themselves
+    stack: Vec<String>,
+}
+
+impl Expander {
+    fn expand(&mut self, value: &mut Value, source: &Source) -> Result<()> {
+        match value {
+            Value::Object(object) => {
+                let Some(reference) = take_directive(object)? else {
+                    for c in object.values_mut() {
+                        self.expand(c, source)?;
+                    }
+                    return Ok(());
+                };
+                let mut target = self.resolve(&reference, source)?;
+                if !object.is_empty() {
+                    let Value::Object(fields) = &mut target else {
+                        Err(Error::BadInclude(format!(
+                            "{:?} isn't an object, so its fields can't be replaced",
+                            reference
+                        )))?
+                    };
+                    for (key, mut c) in std::mem::take(object) {
+                        self.expand(&mut c, source)?;
+                        fields.insert(key, c);
+                    }
+                }
+                *value = target;
+            }
+            Value::Array(array) => {
+                let mut expanded = Vec::with_capacity(array.len());
+                for mut c in array.drain(..) {
+                    let is_directive = is_directive(&c);
+                    self.expand(&mut c, source)?;
+                    match c {
+                        Value::Array(c) if is_directive => expanded.extend(c),
+                        c => expanded.push(c),
+                    }
+                }
+                *array = expanded;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Gets the value a directive stands for, expanded
+    fn resolve(&mut self, reference: &str, source: &Source) -> Result<Value> {
+        let (file, pointer) = reference.split_once('#').unwrap_or((reference, ""));
+        let source = if file.is_empty() {
+            Source {
+                path: source.path.clone(),
+                root: source.root.clone(),
+            }
+        } else {
+            let path = source.path.parent().unwrap_or(Path::new("")).join(file);
+            let path = fs::canonicalize(&path).unwrap_or(path);
+            let root = self.read(&path)?;
+            Source { path, root }
+        };
+
+        let id = format!("{}#{}", source.path.display(), pointer);
+        if self.stack.contains(&id) || self.stack.len() >= MAX_DEPTH {
+            Err(Error::IncludeCycle(reference.to_string()))?
+        }
+        let Some(target) = source.root.pointer(pointer) else {
+            Err(Error::BadInclude(format!("{:?} doesn't exist", reference)))?
+        };
+        let mut target = target.clone();
+
+        self.stack.push(id);
+        self.expand(&mut target, &source)?;
+        self.stack.pop();
+        Ok(target)
+    }
+
+    fn read(&mut self, path: &Path) -> Result<Rc<Value>> {
+        if let Some(c) = self.files.get(path) {
+            return Ok(c.clone());
+        }
+        let text = fs::read_to_string(path).map_err(|e| {
+            Error::BadInclude(format!("couldn't read {:?}: {}", path.as_os_str(), e))
+        })?;
+        let format_ = Format::from_extension(path).unwrap_or_default();
+        let value: Rc<Value> = Rc::new(format::from_str(&text, format_)?);
+        self.files.insert(path.to_path_buf(), value.clone());
+        Ok(value)
+    }
+}
+
+fn is_directive(value: &Value) -> bool {
+    value
+        .as_object()
+        .is_some_and(|c| c.contains_key(INCLUDE) || c.contains_key(REF))
+}
+
+/// Removes the directive from an object, if it has one
+fn take_directive(object: &mut Map<String, Value>) -> Result<Option<String>> {
+    let include = object.shift_remove(INCLUDE);
+    let reference = object.shift_remove(REF);
+    match (include, reference) {
+        (None, None) => Ok(None),
+        (Some(Value::String(c)), None) | (None, Some(Value::String(c))) => Ok(Some(c)),
+        (Some(_), Some(_)) => Err(Error::BadInclude(format!(
+            "an object can't have both {} and {}",
+            INCLUDE, REF
+        ))),
+        (Some(c), None) | (None, Some(c)) => Err(Error::BadInclude(format!(
+            "expected the path of a value, found {}",
+            c
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn expand_json(mut value: Value) -> Result<Value> {
+        expand(&mut value, Path::new("sprite.json"))?;
+        Ok(value)
+    }
+
+    #[test]
+    fn same_file_ref() {
+        let value = expand_json(json!({
+            "data": {
+                "sprites": [
+                    {"parts": [{"pos_x": 1}]},
+                    {"$ref": "#/data/sprites/0"},
+                ],
+            },
+        }))
+        .unwrap();
+        assert_eq!(
+            value["data"]["sprites"][1],
+            json!({"parts": [{"pos_x": 1}]})
+        );
+        assert!(!has_directives(&value));
+    }
+
+    #[test]
+    fn fields_override_the_target() {
+        let value = expand_json(json!({
+            "parts": [
+                {"pos_x": 1, "pos_y": 2},
+                {"$ref": "#/parts/0", "pos_x": 20},
+            ],
+        }))
+        .unwrap();
+        assert_eq!(value["parts"][1], json!({"pos_x": 20, "pos_y": 2}));
+
+        let err = expand_json(json!({
+            "parts": [1, {"$ref": "#/parts/0", "pos_x": 20}],
+        }))
+        .unwrap_err();
+        assert!(matches!(err, Error::BadInclude(_)));
+    }
+
+    #[test]
+    fn arrays_are_spliced_into_arrays() {
+        let value = expand_json(json!({
+            "shared": [{"pos_x": 1}, {"pos_x": 2}],
+            "parts": [{"pos_x": 0}, {"$include": "#/shared"}, {"pos_x": 3}],
+            "copy": {"$ref": "#/shared"},
+        }))
+        .unwrap();
+        let parts: Vec<Value> = (0..4).map(|c| json!({ "pos_x": c })).collect();
+        assert_eq!(value["parts"], Value::from(parts));
+        // outside of an array, the array is kept as is
+        assert_eq!(value["copy"], value["shared"]);
+    }
+
+    #[test]
+    fn cycles_are_caught() {
+        let err = expand_json(json!({"a": {"$ref": "#/b"}, "b": {"$ref": "#/a"}})).unwrap_err();
+        assert!(matches!(err, Error::IncludeCycle(_)));
+        let err = expand_json(json!({"a": [{"$ref": "#/a"}]})).unwrap_err();
+        assert!(matches!(err, Error::IncludeCycle(_)));
+    }
+
+    #[test]
+    fn missing_pointer() {
+        let err = expand_json(json!({"a": {"$ref": "#/sprites/3"}})).unwrap_err();
+        assert!(matches!(err, Error::BadInclude(c) if c.contains("#/sprites/3")));
+        let err = expand_json(json!({"a": {"$ref": 3}})).unwrap_err();
+        assert!(matches!(err, Error::BadInclude(_)));
+    }
+}
@@ -55,27 +55,63 @@
 //! 
 //! # Features
 //! * **`modder_qol`**
+//! * **`formats`**: YAML, TOML and RON support in [`format`]
+//! * **`schema`**: JSON Schema generation in [`schema`]
 
-use serde::{Deserialize, Serialize};
+use serde::Serialize;
 
 /// Contains a model for the generic BXCAD format, as well as
 /// known implementations of it
 pub mod bxcad;
 /// Error handling
 pub mod error;
+/// Text formats for flour files other than JSON
+pub mod format;
+/// Reading JSON with useful error messages
+pub mod json;
+/// Creating and applying BPS patches, so that modified BXCADs can be distributed without
+/// the original files
+pub mod patch;
+/// JSON Schema for flour JSON files
+pub mod schema;
 
 pub(crate) mod bytestream_addon;
 
 pub use bxcad::{bccad::BCCAD, brcad::BRCAD, BXCAD};
 
 /// RGB color
-#[derive(Serialize, Deserialize, Clone, Debug)]
+///
+/// Defaults to black. In JSON, it can be read either as an object with its fields or as a
+/// `"#rrggbb"` string, but it's always written as an object (see [json::colors_to_hex])
+#[derive(Serialize, Clone, Debug, Default, PartialEq, Eq, Hash)]
 pub struct Color {
     pub red: u8,
     pub blue: u8,
     pub green: u8,
 }
 
+impl Color {
+    /// Returns the color as a `#rrggbb` string
+    pub fn to_hex(&self) -> String {
+        format!("#{:02x}{:02x}{:02x}", self.red, self.green, self.blue)
+    }
+
+    /// Parses a `#rrggbb` string (the `#` is optional)
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        let hex = hex.strip_prefix('#').unwrap_or(hex);
+        // from_str_radix also takes a sign, so check the digits first
+        if hex.len() != 6 || !hex.bytes().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+        Some(Self {
+            red: channel(0)?,
+            green: channel(2)?,
+            blue: channel(4)?,
+        })
+    }
+}
+
 /// Variable length string, used in BCCAD labels
 ///
 /// Format is as follows:
@@ -84,3 +120,19 @@ pub struct Color {
 /// * n bytes for string contents
 /// * Padded to 4 bytes
 pub struct VarLenString(String);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn color_hex() {
+        let color = Color::from_hex("#1a2B3c").unwrap();
+        assert_eq!((color.red, color.green, color.blue), (0x1a, 0x2b, 0x3c));
+        assert_eq!(color.to_hex(), "#1a2b3c");
+        assert!(Color::from_hex("1a2b3c").is_some());
+        assert!(Color::from_hex("#+f+f+f").is_none());
+        assert!(Color::from_hex("#1a2b3").is_none());
+        assert!(Color::from_hex("#1a2b3g").is_none());
+    }
+}
@@ -1,19 +1,30 @@
-use clap::{Parser, Subcommand};
+use clap::{ArgGroup, Parser, Subcommand};
 use flour::{
     bxcad::{
-        self,
-        qol::{IndexizedBCCAD, IndexizedBRCAD},
-        BXCADType, BXCADWrapper, BXCAD,
+        self, diff,
+        edit::{Editable, ImportOptions},
+        info::Info,
+        merge, minimal,
+        qol::{Indexizable, IndexizedBCCAD, IndexizedBRCAD},
+        split,
+        stats::Corpus,
+        upgrade, BXCADType, BXCADWrapper, BXCAD,
     },
     error::{Error, Result},
-    BCCAD, BRCAD,
+    format::{self, Format},
+    json::{self, document::Document, include},
+    patch, schema, BCCAD, BRCAD,
 };
-use json_comments::{CommentSettings, StripComments};
+use serde::{de::DeserializeOwned, Serialize};
 use serde_json::Value;
 use std::{
-    fs::File,
-    io::{Read, Write},
-    path::PathBuf,
+    cell::Cell,
+    collections::HashSet,
+    ffi::OsString,
+    fs::{self, File},
+    hash::Hash,
+    io::Write,
+    path::{Path, PathBuf},
 };
 
 #[derive(Parser, Debug)]
@@ -25,6 +36,25 @@ use std::{
 struct Cli {
     #[clap(subcommand)]
     command: Command,
+    /// Fail when a JSON file has fields that flour doesn't know, instead of ignoring them
+    #[clap(long, global = true)]
+    strict: bool,
+    /// Write JSON files in the layout of `flour fmt` when changing them in place
+    #[clap(long, global = true)]
+    canonical: bool,
+}
+
+/// Options for converting several files at once
+#[derive(clap::Args, Debug)]
+struct BatchOptions {
+    /// Location of the file to export, or when converting several files, the directory to
+    /// export them to, keeping the layout of the directories they were found in (by default,
+    /// each file is exported next to the original)
+    #[clap(short, long, parse(from_os_str))]
+    output: Option<PathBuf>,
+    /// Number of files to convert at the same time, 0 being one per CPU core
+    #[clap(short, long, default_value = "1")]
+    jobs: usize,
 }
 
 #[derive(Subcommand, Debug)]
@@ -32,12 +62,14 @@ enum Command {
     /// Convert a BCCAD file into a manually editable JSON file
     #[clap(aliases = &["serialise", "s"])]
     Serialize {
-        #[clap(parse(from_os_str))]
-        /// The B_CAD file to convert
-        bxcad: PathBuf,
-        #[clap(parse(from_os_str))]
-        /// Location of the JSON file to export (optional)
-        json: Option<PathBuf>,
+        /// The B_CAD file to convert, and the location of the JSON file to export (optional).
+        /// Can also be several B_CAD files, directories to search for B_CAD files, or glob
+        /// patterns like "*.bccad". Files ending in .bccad or .brcad that can't be read count
+        /// as failures
+        #[clap(required = true, parse(from_os_str))]
+        paths: Vec<PathBuf>,
+        #[clap(flatten)]
+        batch: BatchOptions,
 
         /// File is a BCCAD
         #[clap(short = 'c', long)]
@@ -57,46 +89,1278 @@ enum Command {
         /// (BRCAD only) Adds labels from label file
         #[clap(short, long, parse(from_os_str))]
         labels: Option<PathBuf>,
-        /// Display indexes next to the sprites
+        /// Display indexes next to the sprites, and refer to sprites by name in animation steps
+        ///
+        /// Sprite names in animation steps are only accepted in indexized files.
         #[clap(short, long, alias = "indexise")]
         indexize: bool,
+        /// Store animations as a map keyed by their names (implies --indexize)
+        #[clap(short = 'n', long, alias = "name-animations")]
+        named_animations: bool,
+        /// Leave out the fields of sprite parts and animation steps that have their default value
+        #[clap(short = 'm', long, alias = "minimal")]
+        omit_defaults: bool,
+        /// Write colors as "#rrggbb" strings instead of objects
+        #[clap(short = 'x', long)]
+        hex_colors: bool,
+        /// Format to write the file in: json, yaml, toml or ron (by default, judging by the
+        /// output file's extension, or JSON)
+        #[clap(short, long)]
+        format: Option<Format>,
+        /// Write a directory with a manifest and a file per sprite and animation instead of a
+        /// single file (its location defaults to the B_CAD file's without the extension)
+        #[clap(short, long)]
+        split: bool,
     },
     /// Convert a JSON file exported by flour back into a BCCAD
     #[clap(aliases = &["deserialise", "d"])]
     Deserialize {
-        /// The JSON file to convert
+        /// The JSON file to convert (or YAML, TOML or RON, judging by the extension), or a
+        /// directory made by `serialize --split`, and the location of the B_CAD file to export
+        /// (optional). Can also be several JSON files, directories to search for JSON files, or
+        /// glob patterns like "*.json"
+        #[clap(required = true, parse(from_os_str))]
+        paths: Vec<PathBuf>,
+        #[clap(flatten)]
+        batch: BatchOptions,
+    },
+    /// Remove the sprites no animation uses from a B_CAD or JSON file
+    Gc {
+        /// The B_CAD or JSON file to clean up
+        #[clap(parse(from_os_str))]
+        input: PathBuf,
+        /// Location of the file to export (optional, overwrites the input file by default)
+        #[clap(parse(from_os_str))]
+        output: Option<PathBuf>,
+        /// List the texture regions that were only used by the removed sprites
+        #[clap(short, long)]
+        regions: bool,
+    },
+    /// Merge identical sprites in a B_CAD or JSON file
+    #[clap(alias = "dedup")]
+    Dedupe {
+        /// The B_CAD or JSON file to clean up
+        #[clap(parse(from_os_str))]
+        input: PathBuf,
+        /// Location of the file to export (optional, overwrites the input file by default)
+        #[clap(parse(from_os_str))]
+        output: Option<PathBuf>,
+    },
+    /// Copy animations, along with the sprites they use, from one B_CAD or JSON file to another
+    ImportAnim {
+        /// The B_CAD or JSON file to copy the animations from
+        #[clap(parse(from_os_str))]
+        src: PathBuf,
+        /// The B_CAD or JSON file to copy the animations to
+        #[clap(parse(from_os_str))]
+        dst: PathBuf,
+        /// Names of the animations to copy (unnamed BRCAD animations can be given by their index)
+        #[clap(required = true)]
+        anims: Vec<String>,
+        /// Location of the file to export (optional, overwrites the destination file by default)
+        #[clap(short, long, parse(from_os_str))]
+        output: Option<PathBuf>,
+        /// Replace animations with the same name in the destination file
+        #[clap(long)]
+        replace: bool,
+        /// Offset to add to the texture regions of the copied sprites
+        #[clap(long, number_of_values = 2, value_names = &["X", "Y"])]
+        region_offset: Option<Vec<u16>>,
+    },
+    /// Show the differences between two B_CAD or JSON files
+    Diff {
+        /// The original B_CAD or JSON file
+        #[clap(parse(from_os_str))]
+        old: PathBuf,
+        /// The modified B_CAD or JSON file
+        #[clap(parse(from_os_str))]
+        new: PathBuf,
+    },
+    /// Merge the changes made to two versions of a B_CAD or JSON file
+    ///
+    /// Can be used as a git merge driver, like so: `flour merge %O %A %B`
+    Merge {
+        /// The common ancestor of both versions
+        #[clap(parse(from_os_str))]
+        base: PathBuf,
+        /// Our version of the file
+        #[clap(parse(from_os_str))]
+        ours: PathBuf,
+        /// Their version of the file
+        #[clap(parse(from_os_str))]
+        theirs: PathBuf,
+        /// Location of the file to export (optional, overwrites our version by default)
+        #[clap(short, long, parse(from_os_str))]
+        output: Option<PathBuf>,
+    },
+    /// Upgrade a JSON file made with an older version of flour to the current format
+    Upgrade {
+        /// The JSON file to upgrade
         #[clap(parse(from_os_str))]
         json: PathBuf,
-        /// Location of the B_CAD file to export (optional)
+        /// Location of the upgraded JSON file to export (optional, overwrites the input by default)
+        #[clap(short, long, parse(from_os_str))]
+        output: Option<PathBuf>,
+    },
+    /// Rewrite JSON files in flour's canonical layout, keeping their comments
+    Fmt {
+        /// The JSON files to format
+        #[clap(required = true, parse(from_os_str))]
+        files: Vec<PathBuf>,
+        /// Don't write anything, only fail if any file isn't formatted
+        #[clap(long)]
+        check: bool,
+    },
+    /// Write a JSON Schema for flour JSON files, so that editors can autocomplete and validate them
+    ///
+    /// By default, the schema accepts any flour JSON file
+    #[clap(group(ArgGroup::new("type").args(&["is-bccad", "is-brcad"])))]
+    Schema {
+        /// Location of the schema to export (optional, prints it by default)
+        #[clap(parse(from_os_str))]
+        output: Option<PathBuf>,
+        /// Only accept BCCAD files
+        #[clap(short = 'c', long)]
+        is_bccad: bool,
+        /// Only accept BRCAD files
+        #[clap(short = 'r', long)]
+        is_brcad: bool,
+        /// Only accept indexized files
+        #[clap(short, long, alias = "indexise", requires = "type")]
+        indexize: bool,
+    },
+    /// Show a summary of the contents of a B_CAD or JSON file
+    Info {
+        /// The B_CAD or JSON file
+        #[clap(parse(from_os_str))]
+        bxcad: PathBuf,
+        /// Print the summary as JSON, for scripts
+        #[clap(short, long)]
+        json: bool,
+    },
+    /// Show how often each value of each field appears across many B_CAD files, and which other
+    /// fields it goes along with
+    Stats {
+        /// The B_CAD files, or directories to search for B_CAD files
+        #[clap(required = true, parse(from_os_str))]
+        paths: Vec<PathBuf>,
+        /// Only show the fields whose purpose is unknown
+        #[clap(short, long)]
+        unknowns: bool,
+    },
+    /// List every field of a B_CAD file with its offset, raw bytes and value
+    Dump {
+        /// The B_CAD file to dump
+        #[clap(parse(from_os_str))]
+        bxcad: PathBuf,
+        /// Location of the dump to export (optional, prints it by default)
+        #[clap(parse(from_os_str))]
+        output: Option<PathBuf>,
+        /// File is a BCCAD
+        #[clap(short = 'c', long)]
+        is_bccad: bool,
+        /// File is a BRCAD
+        #[clap(short = 'r', long, conflicts_with = "is-bccad")]
+        is_brcad: bool,
+    },
+    /// Create or apply BPS patches for B_CAD files
+    Patch {
+        #[clap(subcommand)]
+        command: PatchCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum PatchCommand {
+    /// Create a patch that turns an original B_CAD file into a modified B_CAD or JSON file
+    Create {
+        /// The original B_CAD file
+        #[clap(parse(from_os_str))]
+        original: PathBuf,
+        /// The modified B_CAD or JSON file
+        #[clap(parse(from_os_str))]
+        modified: PathBuf,
+        /// Location of the patch to export (optional)
         #[clap(parse(from_os_str))]
-        bxcad: Option<PathBuf>,
+        patch: Option<PathBuf>,
     },
+    /// Apply a patch to the original B_CAD file it was made for
+    Apply {
+        /// The original B_CAD file
+        #[clap(parse(from_os_str))]
+        original: PathBuf,
+        /// The patch to apply
+        #[clap(parse(from_os_str))]
+        patch: PathBuf,
+        /// Location of the patched B_CAD file to export (optional, defaults to the original with a
+        /// `.patched` extension added before its own)
+        #[clap(parse(from_os_str))]
+        output: Option<PathBuf>,
+    },
+}
+
+/// A BXCAD of any of the types supported by the flour binary
+#[allow(clippy::upper_case_acronyms)]
+enum AnyBXCAD {
+    BCCAD(BCCAD),
+    BRCAD(BRCAD),
+}
+
+/// The way a BXCAD was stored, so that it can be written back the same way
+enum Storage {
+    Binary,
+    Json(Box<JsonOptions>),
+}
+
+/// How to write a BXCAD as flour JSON
+#[derive(Default)]
+struct JsonOptions {
+    indexize: bool,
+    named_animations: bool,
+    omit_defaults: bool,
+    hex_colors: bool,
+    format: Format,
+    /// Write a directory with a file per sprite and animation, see [split::split]
+    split: bool,
+    /// Use the layout of `flour fmt`
+    canonical: bool,
+    /// A JSON file to keep the comments of
+    comments: Option<Document>,
+    /// Whether the file had `$include` or `$ref` directives, which get expanded when written
+    directives: bool,
 }
 
-fn main() -> Result<()> {
+/// Reads a flour JSON file, or a file in another format if its extension says so, or a
+/// directory made by `serialize --split`
+fn read_json(path: &Path, strict: bool) -> Result<(AnyBXCAD, Storage)> {
+    if path.is_dir() {
+        return read_split(path, strict);
+    }
+    let text = fs::read_to_string(path)?;
+    let format_ = Format::from_extension(path).unwrap_or_default();
+    let mut value: Value = format::from_str(&text, format_)?;
+    let expanded = include::expand(&mut value, path)?;
+    if format_ == Format::Json && !expanded {
+        return parse_json(&text, strict);
+    }
+
+    // the directives and comments can't be kept if the file is written back
+    let (bxcad, mut storage) = parse_value(&value, strict)?;
+    if let Storage::Json(options) = &mut storage {
+        options.format = format_;
+        options.comments = None;
+        options.directives = expanded;
+    }
+    Ok((bxcad, storage))
+}
+
+/// The manifest file of a directory made by `serialize --split`, if it is one
+fn find_manifest(dir: &Path) -> Option<PathBuf> {
+    [Format::Json, Format::Yaml, Format::Toml, Format::Ron]
+        .into_iter()
+        .map(|c| dir.join(format!("{}.{}", split::MANIFEST, c.extension())))
+        .find(|c| c.is_file())
+}
+
+/// Reads a directory made by `serialize --split`
+fn read_split(dir: &Path, strict: bool) -> Result<(AnyBXCAD, Storage)> {
+    let manifest = find_manifest(dir).ok_or_else(|| Error::NoManifest(dir.to_path_buf()))?;
+    let directives = Cell::new(false);
+    let read = |path: &Path| -> Result<Value> {
+        let format_ = Format::from_extension(path).unwrap_or_default();
+        let mut value = format::from_str(&fs::read_to_string(path)?, format_)?;
+        if include::expand(&mut value, path)? {
+            directives.set(true);
+        }
+        Ok(value)
+    };
+    let read =
+        |path: &Path| read(path).map_err(|c| Error::InSplitFile(path.to_path_buf(), Box::new(c)));
+
+    let mut value_wrapper = read(&manifest)?;
+    split::join(&mut value_wrapper, |c| read(&dir.join(c)))?;
+    let (bxcad, mut storage) = parse_value(&value_wrapper, strict)?;
+    if let Storage::Json(options) = &mut storage {
+        options.format = Format::from_extension(&manifest).unwrap_or_default();
+        options.split = true;
+        options.comments = None;
+        options.directives = directives.get();
+    }
+    Ok((bxcad, storage))
+}
+
+/// Parses a flour JSON file that was read into a value some other way than from JSON text,
+/// where positions in the text wouldn't match anything the user wrote
+fn parse_value(value_wrapper: &Value, strict: bool) -> Result<(AnyBXCAD, Storage)> {
+    parse_json(&serde_json::to_string_pretty(value_wrapper)?, strict).map_err(without_location)
+}
+
+/// Leaves out the position of a JSON error, for JSON text written by flour itself
+fn without_location(err: Error) -> Error {
+    match err {
+        Error::BadJson(c) => Error::BadJson(Box::new(c.without_location())),
+        c => c,
+    }
+}
+
+/// Parses the text of a flour JSON file, which may have comments
+fn parse_json(json_: &str, strict: bool) -> Result<(AnyBXCAD, Storage)> {
+    let document = Document::parse(json_)?;
+    let value_wrapper = document.value();
+
+    let Some(bxcad_type) = value_wrapper.get("bxcad_type") else {
+        Err(Error::NotFlour)?
+    };
+    let Some(bxcad_type) = bxcad_type.as_str() else {
+        Err(Error::NotFlour)?
+    };
+    let bxcad_type: BXCADType = serde_json::from_str(&format!("\"{}\"", bxcad_type))?;
+
+    let indexized = if let Some(bxcad_type) = value_wrapper.get("indexize") {
+        let Some(c) = bxcad_type.as_bool() else {
+            Err(Error::NotFlour)?
+        };
+        c
+    } else {
+        false
+    };
+    if !indexized {
+        check_no_sprite_names(value_wrapper)?;
+    }
+    let named_animations = indexized
+        && value_wrapper
+            .get("data")
+            .and_then(|c| c.get("animations"))
+            .is_some_and(Value::is_object);
+    let storage = Storage::Json(Box::new(JsonOptions {
+        indexize: indexized,
+        named_animations,
+        omit_defaults: value_wrapper
+            .get("omit_defaults")
+            .and_then(Value::as_bool)
+            .unwrap_or_default(),
+        hex_colors: value_wrapper
+            .get("hex_colors")
+            .and_then(Value::as_bool)
+            .unwrap_or_default(),
+        format: Format::Json,
+        split: false,
+        canonical: false,
+        comments: Some(document),
+        directives: false,
+    }));
+
+    let bxcad = match bxcad_type {
+        BXCADType::BCCAD => AnyBXCAD::BCCAD(if indexized {
+            let bxcad_wrapper: BXCADWrapper<IndexizedBCCAD> = from_json(json_, strict)?;
+            bxcad_wrapper.indexized_to_bxcad()?
+        } else {
+            let bxcad_wrapper: BXCADWrapper<BCCAD> = from_json(json_, strict)?;
+            bxcad_wrapper.to_bxcad()?
+        }),
+        BXCADType::BRCAD => AnyBXCAD::BRCAD(if indexized {
+            let bxcad_wrapper: BXCADWrapper<IndexizedBRCAD> = from_json(json_, strict)?;
+            bxcad_wrapper.indexized_to_bxcad()?
+        } else {
+            let bxcad_wrapper: BXCADWrapper<BRCAD> = from_json(json_, strict)?;
+            bxcad_wrapper.to_bxcad()?
+        }),
+        //  BXCADType::Custom(_) => todo!(),
+        _ => unimplemented!(),
+    };
+    Ok((bxcad, storage))
+}
+
+/// Fails if an animation step of a non-indexized file refers to its sprite by name, which
+/// would otherwise only give a type error
+fn check_no_sprite_names(value_wrapper: &Value) -> Result<()> {
+    let animations = value_wrapper
+        .get("data")
+        .and_then(|c| c.get("animations"))
+        .and_then(Value::as_array);
+    let steps = animations
+        .into_iter()
+        .flatten()
+        .filter_map(|c| c.get("steps").and_then(Value::as_array))
+        .flatten();
+    for step in steps {
+        if let Some(name) = step.get("sprite").and_then(Value::as_str) {
+            Err(Error::SpriteNameNotIndexized(name.to_string()))?
+        }
+    }
+    Ok(())
+}
+
+/// Deserializes JSON text, rejecting unknown fields if `strict` is set
+fn from_json<T: DeserializeOwned + Serialize>(json_: &str, strict: bool) -> Result<T> {
+    if strict {
+        json::from_str_strict(json_)
+    } else {
+        json::from_str(json_)
+    }
+}
+
+/// Lists the given files, and every file inside the given directories and their subdirectories
+fn files_in(paths: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    for path in paths {
+        if !path.is_dir() {
+            files.push(path.clone());
+            continue;
+        }
+        let mut entries = fs::read_dir(path)?
+            .map(|c| Ok(c?.path()))
+            .collect::<Result<Vec<_>>>()?;
+        entries.sort();
+        files.extend(files_in(&entries)?);
+    }
+    Ok(files)
+}
+
+/// Fails if writing a file in place would expand its `$include` and `$ref` directives
+fn check_in_place(storage: &Storage, input: &Path, output: &Path) -> Result<()> {
+    let Storage::Json(options) = storage else {
+        return Ok(());
+    };
+    if options.directives && same_file(input, output) {
+        Err(Error::WouldLoseDirectives(input.to_path_buf()))?
+    }
+    Ok(())
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    a == b || matches!((a.canonicalize(), b.canonicalize()), (Ok(a), Ok(b)) if a == b)
+}
+
+/// Sets whether a JSON file gets written back in the canonical layout (see `--canonical`)
+fn with_layout(mut storage: Storage, canonical: bool) -> Storage {
+    if let Storage::Json(options) = &mut storage {
+        options.canonical = canonical;
+    }
+    storage
+}
+
+/// Reads a B_CAD file, or a flour JSON file if it's not a known B_CAD
+fn read_any(path: &Path, strict: bool) -> Result<(AnyBXCAD, Storage)> {
+    if path.is_dir() {
+        return read_split(path, strict);
+    }
+    let mut in_file = File::open(path)?;
+    Ok(match bxcad::get_bxcad_type(&mut in_file)? {
+        Some(BXCADType::BCCAD) => (
+            AnyBXCAD::BCCAD(BCCAD::from_binary(&mut in_file)?),
+            Storage::Binary,
+        ),
+        Some(BXCADType::BRCAD) => (
+            AnyBXCAD::BRCAD(BRCAD::from_binary(&mut in_file)?),
+            Storage::Binary,
+        ),
+        Some(c) => Err(Error::NonImplementedFeature(format!("BXCAD type {:?}", c)))?,
+        None => read_json(path, strict)?,
+    })
+}
+
+/// Creates the flour JSON for a BXCAD
+fn to_json<X>(bxcad: X, options: &JsonOptions) -> Result<String>
+where
+    X: Indexizable + Serialize,
+    X::Indexized: Serialize,
+{
+    to_text(to_value(bxcad, options)?, options)
+}
+
+/// Creates the flour JSON for a BXCAD, as a value
+fn to_value<X>(bxcad: X, options: &JsonOptions) -> Result<Value>
+where
+    X: Indexizable + Serialize,
+    X::Indexized: Serialize,
+{
+    let mut value_wrapper = if options.named_animations {
+        serde_json::to_value(with_options(
+            BXCADWrapper::from_bxcad_indexize_named(bxcad),
+            options,
+        ))?
+    } else if options.indexize {
+        serde_json::to_value(with_options(
+            BXCADWrapper::from_bxcad_indexize(bxcad),
+            options,
+        ))?
+    } else {
+        serde_json::to_value(with_options(BXCADWrapper::from_bxcad(bxcad), options))?
+    };
+    if options.omit_defaults {
+        minimal::omit_defaults(&mut value_wrapper)?;
+    }
+    if options.hex_colors {
+        json::colors_to_hex(&mut value_wrapper);
+    }
+    Ok(value_wrapper)
+}
+
+/// Records in the wrapper the options that change how the rest of the file is written
+fn with_options<X>(mut wrapper: BXCADWrapper<X>, options: &JsonOptions) -> BXCADWrapper<X> {
+    wrapper.omit_defaults = options.omit_defaults;
+    wrapper.hex_colors = options.hex_colors;
+    wrapper
+}
+
+/// Writes flour JSON (or part of it) as text in the format of the options
+fn to_text(value: Value, options: &JsonOptions) -> Result<String> {
+    if options.format != Format::Json {
+        return format::to_string(&value, options.format);
+    }
+    let mut document = options.comments.clone().unwrap_or_default();
+    document.set_value(value);
+    Ok(if options.canonical {
+        document.to_canonical_string()
+    } else {
+        document.to_string()
+    })
+}
+
+/// Writes a BXCAD to the given path, stored in the given way
+fn write_bxcad<X>(bxcad: X, storage: Storage, path: &Path) -> Result<()>
+where
+    X: Indexizable + Serialize,
+    X::Indexized: Serialize,
+{
+    match storage {
+        Storage::Binary => {
+            let mut out_file = File::create(path)?;
+            bxcad.to_binary(&mut out_file)?;
+        }
+        Storage::Json(options) if options.split => {
+            write_split(to_value(bxcad, &options)?, &options, path)?;
+        }
+        Storage::Json(options) => {
+            let json_ = to_json(bxcad, &options)?;
+            let mut out_file = File::create(path)?;
+            writeln!(out_file, "{}", json_)?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes flour JSON split into a directory, removing the files of sprites and animations that
+/// aren't there anymore
+fn write_split(value_wrapper: Value, options: &JsonOptions, dir: &Path) -> Result<()> {
+    let extension = options.format.extension();
+    let files = split::split(value_wrapper, extension);
+    let paths: HashSet<PathBuf> = files.iter().map(|(c, _)| dir.join(c)).collect();
+
+    for subdir in [split::SPRITES_DIR, split::ANIMATIONS_DIR] {
+        let subdir = dir.join(subdir);
+        if subdir.is_dir() {
+            for entry in fs::read_dir(&subdir)? {
+                let path = entry?.path();
+                if path.extension().is_some_and(|c| c == extension) && !paths.contains(&path) {
+                    fs::remove_file(path)?;
+                }
+            }
+        }
+        fs::create_dir_all(subdir)?;
+    }
+    for (path, value) in files {
+        let mut out_file = File::create(dir.join(path))?;
+        writeln!(out_file, "{}", to_text(value, options)?)?;
+    }
+    Ok(())
+}
+
+/// Implementation of the `gc` subcommand for a specific BXCAD type
+fn gc<X>(mut bxcad: X, mut storage: Storage, output: &Path, regions: bool) -> Result<()>
+where
+    X: Editable + Indexizable + Serialize,
+    X::Indexized: Serialize,
+{
+    let removed = bxcad.remove_unused_sprites();
+    for (i, _) in &removed {
+        println!("Removed sprite {}", i);
+    }
+    if regions {
+        for region in bxcad.unused_regions(&removed) {
+            println!(
+                "Unused texture region: x={} y={} width={} height={}",
+                region.x, region.y, region.width, region.height
+            );
+        }
+    }
+    println!("Removed {} unused sprites", removed.len());
+    let removed: Vec<_> = removed.iter().map(|(i, _)| *i).collect();
+    remove_sprite_comments(&mut storage, &removed);
+    write_bxcad(bxcad, storage, output)
+}
+
+/// Implementation of the `import-anim` subcommand for a specific BXCAD type
+fn import_anim<X>(
+    src: X,
+    mut dst: X,
+    anims: &[String],
+    options: &ImportOptions,
+    storage: Storage,
+    output: &Path,
+) -> Result<()>
+where
+    X: Editable + Indexizable + Serialize,
+    X::Sprite: Clone,
+    X::Animation: Clone,
+    X::Indexized: Serialize,
+{
+    let sprite_count = dst.sprites().len();
+    let anims: Vec<&str> = anims.iter().map(String::as_str).collect();
+    dst.import_animations(&src, &anims, options)?;
+    println!(
+        "Imported {} animations and {} sprites",
+        anims.len(),
+        dst.sprites().len() - sprite_count
+    );
+    write_bxcad(dst, storage, output)
+}
+
+/// Implementation of the `merge` subcommand for a specific BXCAD type
+fn merge<X>(base: X, ours: X, theirs: X, storage: Storage, output: &Path) -> Result<()>
+where
+    X: Indexizable + Serialize + DeserializeOwned,
+    X::Indexized: Serialize,
+{
+    let merge = merge::merge(&base, &ours, &theirs)?;
+    for conflict in &merge.conflicts {
+        println!("Conflict in {}", conflict);
+    }
+    write_bxcad(merge.merged, storage, output)?;
+    if !merge.conflicts.is_empty() {
+        Err(Error::MergeConflicts(merge.conflicts.len()))?
+    }
+    Ok(())
+}
+
+/// Implementation of the `dedupe` subcommand for a specific BXCAD type
+fn dedupe<X>(mut bxcad: X, mut storage: Storage, output: &Path) -> Result<()>
+where
+    X: Editable + Indexizable + Serialize,
+    X::Sprite: Eq + Hash,
+    X::Indexized: Serialize,
+{
+    let merged = bxcad.merge_duplicate_sprites();
+    for (i, into) in &merged {
+        println!("Merged sprite {} into sprite {}", i, into);
+    }
+    println!("Merged {} duplicate sprites", merged.len());
+    let removed: Vec<_> = merged.iter().map(|(i, _)| *i).collect();
+    remove_sprite_comments(&mut storage, &removed);
+    write_bxcad(bxcad, storage, output)
+}
+
+/// Keeps the comments of a JSON file next to the same sprites after some are removed
+fn remove_sprite_comments(storage: &mut Storage, removed: &[u16]) {
+    if let Storage::Json(options) = storage {
+        if let Some(document) = &mut options.comments {
+            document.remove_sprites(removed);
+        }
+    }
+}
+
+/// Options of the `serialize` subcommand that apply to every file
+struct SerializeOptions {
+    is_bccad: bool,
+    is_brcad: bool,
+    labels: Option<PathBuf>,
+    indexize: bool,
+    named_animations: bool,
+    omit_defaults: bool,
+    hex_colors: bool,
+    format: Option<Format>,
+    split: bool,
+}
+
+/// Implementation of the `serialize` subcommand for a single file, returning where it was written
+fn serialize(bxcad: &Path, output: Output, options: &SerializeOptions) -> Result<PathBuf> {
+    let format_ = options
+        .format
+        .or_else(|| match (&output, options.split) {
+            (Output::Path(c), false) => Format::from_extension(c),
+            _ => None,
+        })
+        .unwrap_or_default();
+    let json = match output {
+        Output::Path(c) => c,
+        Output::Like(mut c) => {
+            c.set_extension(if options.split {
+                ""
+            } else {
+                format_.extension()
+            });
+            c
+        }
+    };
+
+    let mut in_file = File::open(bxcad)?;
+    // keep the comments of the JSON file being replaced, if there is one
+    let comments = match format_ {
+        Format::Json if !options.split => fs::read_to_string(&json)
+            .ok()
+            .and_then(|c| Document::parse(&c).ok()),
+        _ => None,
+    };
+    let json_options = JsonOptions {
+        indexize: options.indexize,
+        named_animations: options.named_animations,
+        omit_defaults: options.omit_defaults,
+        hex_colors: options.hex_colors,
+        format: format_,
+        split: options.split,
+        canonical: false,
+        comments,
+        directives: false,
+    };
+
+    let bxcad_type = if options.is_bccad {
+        BXCADType::BCCAD
+    } else if options.is_brcad {
+        BXCADType::BRCAD
+    } else {
+        bxcad::get_bxcad_type(&mut in_file)?.ok_or(Error::NotBXCAD)?
+    };
+
+    if options.labels.is_some() && bxcad_type != BXCADType::BRCAD {
+        Err(Error::LabelsOnNotBRCAD)?
+    }
+
+    let storage = Storage::Json(Box::new(json_options));
+    match bxcad_type {
+        BXCADType::BCCAD => {
+            let bccad = BCCAD::from_binary(&mut in_file)?;
+            write_bxcad(bccad, storage, &json)?;
+        }
+        BXCADType::BRCAD => {
+            let mut brcad = BRCAD::from_binary(&mut in_file)?;
+            if let Some(c) = &options.labels {
+                let mut labels_file = File::open(c)?;
+                brcad.apply_labels(&mut labels_file)?;
+            }
+            write_bxcad(brcad, storage, &json)?;
+        }
+        //  BXCADType::Custom(_) => Err(Error::NonImplementedFeature(
+        //      "custom BXCAD types".to_string(),
+        //  ))?,
+        c => Err(Error::NonImplementedFeature(format!("BXCAD type {:?}", c)))?,
+    }
+    Ok(json)
+}
+
+/// Implementation of the `deserialize` subcommand for a single file, returning where it was
+/// written
+fn deserialize(json: &Path, output: Output, strict: bool) -> Result<PathBuf> {
+    let (bxcad_, _) = read_json(json, strict)?;
+
+    let bxcad = match output {
+        Output::Path(c) => c,
+        Output::Like(mut c) => {
+            c.set_extension(match &bxcad_ {
+                AnyBXCAD::BCCAD(_) => "bccad",
+                AnyBXCAD::BRCAD(_) => "brcad",
+            });
+            c
+        }
+    };
+
+    // file is created after (de)serializing, see issue #1
+    match bxcad_ {
+        AnyBXCAD::BCCAD(c) => write_bxcad(c, Storage::Binary, &bxcad)?,
+        AnyBXCAD::BRCAD(c) => write_bxcad(c, Storage::Binary, &bxcad)?,
+    }
+    Ok(bxcad)
+}
+
+/// Where to write a converted file
+#[derive(Clone)]
+enum Output {
+    /// This exact location
+    Path(PathBuf),
+    /// This location, with the extension changed to that of the converted file
+    Like(PathBuf),
+}
+
+/// The files to convert in `serialize` or `deserialize`, and where to write each of them
+enum Plan {
+    /// A single file, which stops flour if it fails like in every other subcommand
+    Single(PathBuf, Output),
+    /// Several files, which are all converted even if some of them fail
+    Batch(Vec<(PathBuf, Output)>),
+}
+
+/// Figures out which files to convert given the paths passed to `serialize` or `deserialize`,
+/// and whether each path is itself a file to convert
+///
+/// For compatibility with older versions of flour, a second path that isn't something to convert
+/// is where to write the only file to convert.
+fn plan(
+    paths: &[PathBuf],
+    options: &BatchOptions,
+    is_input: &dyn Fn(&Path) -> bool,
+) -> Result<Plan> {
+    let is_single = |c: &Path| !is_pattern(c) && (c.is_file() || is_input(c));
+    match paths {
+        [input] if is_single(input) => {
+            let output = match &options.output {
+                Some(c) => Output::Path(c.clone()),
+                None => Output::Like(input.clone()),
+            };
+            return Ok(Plan::Single(input.clone(), output));
+        }
+        [input, output]
+            if options.output.is_none()
+                && is_single(input)
+                && !is_pattern(output)
+                && !is_input(output)
+                && (!output.is_dir() || find_manifest(output).is_some()) =>
+        {
+            return Ok(Plan::Single(input.clone(), Output::Path(output.clone())));
+        }
+        _ => {}
+    }
+
+    let mut files = vec![];
+    for path in paths {
+        for (input, relative) in find_inputs(path, is_input)? {
+            let output = match &options.output {
+                Some(c) => c.join(relative),
+                None => input.clone(),
+            };
+            files.push((input, Output::Like(output)));
+        }
+    }
+    Ok(Plan::Batch(files))
+}
+
+/// Lists the files to convert in a path, which is either a file, a directory to search, or a
+/// glob pattern, along with their location relative to the path
+fn find_inputs(path: &Path, is_input: &dyn Fn(&Path) -> bool) -> Result<Vec<(PathBuf, PathBuf)>> {
+    fn search(
+        dir: &Path,
+        base: &Path,
+        is_input: &dyn Fn(&Path) -> bool,
+        out: &mut Vec<(PathBuf, PathBuf)>,
+    ) -> Result<()> {
+        let mut entries = fs::read_dir(dir)?
+            .map(|c| Ok(c?.path()))
+            .collect::<Result<Vec<_>>>()?;
+        entries.sort();
+        for entry in entries {
+            if is_input(&entry) {
+                let relative = entry.strip_prefix(base).unwrap_or(&entry).to_path_buf();
+                out.push((entry, relative));
+            } else if entry.is_dir() {
+                search(&entry, base, is_input, out)?;
+            }
+        }
+        Ok(())
+    }
+
+    let mut out = vec![];
+    if is_pattern(path) {
+        let base: PathBuf = path
+            .components()
+            .take_while(|c| !is_pattern(Path::new(c.as_os_str())))
+            .collect();
+        let pattern = path.to_string_lossy();
+        let matches = glob::glob(&pattern)
+            .map_err(|e| Error::BadPattern(pattern.to_string(), e.to_string()))?;
+        for entry in matches {
+            let entry = entry.map_err(std::io::Error::from)?;
+            if is_input(&entry) {
+                let relative = entry.strip_prefix(&base).unwrap_or(&entry).to_path_buf();
+                out.push((entry, relative));
+            } else if entry.is_dir() {
+                search(&entry, &base, is_input, &mut out)?;
+            }
+        }
+    } else if path.is_dir() && !is_input(path) {
+        search(path, path, is_input, &mut out)?;
+    } else {
+        let relative = PathBuf::from(path.file_name().unwrap_or(path.as_os_str()));
+        out.push((path.to_path_buf(), relative));
+    }
+    Ok(out)
+}
+
+/// Whether a path is a glob pattern rather than the location of a file
+fn is_pattern(path: &Path) -> bool {
+    path.to_str().is_some_and(|c| c.contains(['*', '?', '[']))
+}
+
+/// Converts the files of a [Plan], printing the result for each one
+fn convert(
+    plan: Plan,
+    jobs: usize,
+    verb: &str,
+    convert: impl Fn(&Path, Output) -> Result<PathBuf> + Sync,
+) -> Result<()> {
+    let files = match plan {
+        Plan::Single(input, output) => {
+            let output = convert(&input, output)?;
+            println!(
+                "{} {:?} to {:?}",
+                verb,
+                input.into_os_string(),
+                output.into_os_string()
+            );
+            return Ok(());
+        }
+        Plan::Batch(c) => c,
+    };
+
+    let convert_one = |(input, output): &(PathBuf, Output)| {
+        let result = (|| {
+            if let Output::Like(c) = output {
+                if let Some(c) = c.parent() {
+                    fs::create_dir_all(c)?;
+                }
+            }
+            convert(input, output.clone())
+        })();
+        match result {
+            Ok(c) => {
+                println!(
+                    "{} {:?} to {:?}",
+                    verb,
+                    input.as_os_str(),
+                    c.into_os_string()
+                );
+                true
+            }
+            Err(e) => {
+                eprintln!("Failed to convert {:?}: {}", input.as_os_str(), e);
+                false
+            }
+        }
+    };
+    let failed = match jobs {
+        1 => files.iter().filter(|c| !convert_one(c)).count(),
+        _ => count_failures_parallel(&files, jobs, convert_one)?,
+    };
+
+    println!(
+        "Converted {} of {} files",
+        files.len() - failed,
+        files.len()
+    );
+    if failed != 0 {
+        Err(Error::FailedFiles(failed))?
+    }
+    Ok(())
+}
+
+/// Runs a conversion on several files at the same time, returning how many of them failed
+#[cfg(feature = "parallel")]
+fn count_failures_parallel<T: Sync>(
+    files: &[T],
+    jobs: usize,
+    convert: impl Fn(&T) -> bool + Sync,
+) -> Result<usize> {
+    use rayon::prelude::*;
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(jobs)
+        .build()
+        .map_err(std::io::Error::other)?;
+    Ok(pool.install(|| files.par_iter().filter(|c| !convert(c)).count()))
+}
+
+#[cfg(not(feature = "parallel"))]
+fn count_failures_parallel<T>(
+    _files: &[T],
+    _jobs: usize,
+    _convert: impl Fn(&T) -> bool,
+) -> Result<usize> {
+    Err(Error::NonImplementedFeature(
+        "converting several files at the same time without the `parallel` feature".to_string(),
+    ))
+}
+
+fn main() {
+    if let Err(err) = run() {
+        eprintln!("Error: {}", err);
+        std::process::exit(1);
+    }
+}
+
+fn run() -> Result<()> {
     let cli = Cli::parse();
+    let strict = cli.strict;
 
     match cli.command {
         Command::Serialize {
-            bxcad,
-            json,
+            paths,
+            batch,
             is_bccad,
             is_brcad,
             labels,
             indexize,
+            named_animations,
+            omit_defaults,
+            hex_colors,
+            format: format_,
+            split,
             ..
         } => {
-            let json = match json {
-                Some(c) => c,
-                None => {
-                    let mut p = bxcad.clone();
-                    p.set_extension("json");
-                    p
+            // files named like B_CADs are converted even if they don't look like one, so that
+            // broken ones fail instead of being left out without a word
+            let is_input = |path: &Path| {
+                path.is_file()
+                    && (path.extension().is_some_and(|c| {
+                        c.eq_ignore_ascii_case("bccad") || c.eq_ignore_ascii_case("brcad")
+                    }) || File::open(path)
+                        .ok()
+                        .and_then(|mut c| bxcad::get_bxcad_type(&mut c).ok().flatten())
+                        .is_some())
+            };
+            let plan = plan(&paths, &batch, &is_input)?;
+            if labels.is_some() && matches!(plan, Plan::Batch(_)) {
+                Err(Error::LabelsOnSeveralFiles)?
+            }
+            let options = SerializeOptions {
+                is_bccad,
+                is_brcad,
+                labels,
+                indexize,
+                named_animations,
+                omit_defaults,
+                hex_colors,
+                format: format_,
+                split,
+            };
+            convert(plan, batch.jobs, "Serialized", |input, output| {
+                serialize(input, output, &options)
+            })?;
+        }
+        Command::Deserialize { paths, batch } => {
+            let is_input = |path: &Path| {
+                (path.is_file() && Format::from_extension(path).is_some())
+                    || find_manifest(path).is_some()
+            };
+            let plan = plan(&paths, &batch, &is_input)?;
+            convert(plan, batch.jobs, "Deserialized", |input, output| {
+                deserialize(input, output, strict)
+            })?;
+        }
+        Command::Gc {
+            input,
+            output,
+            regions,
+        } => {
+            let (bxcad, storage) = read_any(&input, strict)?;
+            let storage = with_layout(storage, cli.canonical);
+            let output = output.unwrap_or_else(|| input.clone());
+            check_in_place(&storage, &input, &output)?;
+            match bxcad {
+                AnyBXCAD::BCCAD(c) => gc(c, storage, &output, regions)?,
+                AnyBXCAD::BRCAD(c) => gc(c, storage, &output, regions)?,
+            }
+        }
+        Command::Dedupe { input, output } => {
+            let (bxcad, storage) = read_any(&input, strict)?;
+            let storage = with_layout(storage, cli.canonical);
+            let output = output.unwrap_or_else(|| input.clone());
+            check_in_place(&storage, &input, &output)?;
+            match bxcad {
+                AnyBXCAD::BCCAD(c) => dedupe(c, storage, &output)?,
+                AnyBXCAD::BRCAD(c) => dedupe(c, storage, &output)?,
+            }
+        }
+        Command::ImportAnim {
+            src,
+            dst,
+            anims,
+            output,
+            replace,
+            region_offset,
+        } => {
+            let (src, _) = read_any(&src, strict)?;
+            let (dst_, storage) = read_any(&dst, strict)?;
+            let storage = with_layout(storage, cli.canonical);
+            let output = output.unwrap_or_else(|| dst.clone());
+            check_in_place(&storage, &dst, &output)?;
+            let region_offset = match region_offset.as_deref() {
+                Some(&[x, y]) => (x, y),
+                _ => (0, 0),
+            };
+            let options = ImportOptions {
+                replace,
+                region_offset,
+            };
+            match (src, dst_) {
+                (AnyBXCAD::BCCAD(src), AnyBXCAD::BCCAD(dst)) => {
+                    import_anim(src, dst, &anims, &options, storage, &output)?
+                }
+                (AnyBXCAD::BRCAD(src), AnyBXCAD::BRCAD(dst)) => {
+                    import_anim(src, dst, &anims, &options, storage, &output)?
                 }
+                _ => Err(Error::DifferentBXCADTypes)?,
+            }
+        }
+        Command::Diff { old, new } => {
+            let differences = match (read_any(&old, strict)?.0, read_any(&new, strict)?.0) {
+                (AnyBXCAD::BCCAD(old), AnyBXCAD::BCCAD(new)) => diff::diff(&old, &new)?,
+                (AnyBXCAD::BRCAD(old), AnyBXCAD::BRCAD(new)) => diff::diff(&old, &new)?,
+                _ => Err(Error::DifferentBXCADTypes)?,
             };
+            for difference in differences {
+                println!("{}", difference);
+            }
+        }
+        Command::Merge {
+            base,
+            ours,
+            theirs,
+            output,
+        } => {
+            let (base, _) = read_any(&base, strict)?;
+            let (ours_, storage) = read_any(&ours, strict)?;
+            let storage = with_layout(storage, cli.canonical);
+            let (theirs, _) = read_any(&theirs, strict)?;
+            let output = output.unwrap_or_else(|| ours.clone());
+            check_in_place(&storage, &ours, &output)?;
+            match (base, ours_, theirs) {
+                (AnyBXCAD::BCCAD(base), AnyBXCAD::BCCAD(ours), AnyBXCAD::BCCAD(theirs)) => {
+                    merge(base, ours, theirs, storage, &output)?
+                }
+                (AnyBXCAD::BRCAD(base), AnyBXCAD::BRCAD(ours), AnyBXCAD::BRCAD(theirs)) => {
+                    merge(base, ours, theirs, storage, &output)?
+                }
+                _ => Err(Error::DifferentBXCADTypes)?,
+            }
+        }
+        Command::Upgrade { json, output } => {
+            let output = output.unwrap_or_else(|| json.clone());
+            let mut document = Document::parse(&fs::read_to_string(&json)?)?;
+            let mut value_wrapper = document.value().clone();
+            if include::expand(&mut value_wrapper, &json)? {
+                if same_file(&json, &output) {
+                    Err(Error::WouldLoseDirectives(json.clone()))?
+                }
+                // the comments can't be kept along with the expanded directives
+                document = Document::default();
+            }
+            let version = upgrade::upgrade(&mut value_wrapper)?;
+            document.set_value(value_wrapper);
+            let (bxcad, storage) =
+                parse_json(&document.to_string(), strict).map_err(without_location)?;
+            let storage = with_layout(storage, cli.canonical);
+            match bxcad {
+                AnyBXCAD::BCCAD(c) => write_bxcad(c, storage, &output)?,
+                AnyBXCAD::BRCAD(c) => write_bxcad(c, storage, &output)?,
+            }
+            println!(
+                "Upgraded {:?} from flour {} to {} as {:?}",
+                json.into_os_string(),
+                version,
+                env!("CARGO_PKG_VERSION"),
+                output.into_os_string()
+            );
+        }
+        Command::Fmt { files, check } => {
+            let mut unformatted = 0;
+            for file in files {
+                let json_ = fs::read_to_string(&file)?;
+                // formatting goes through the BXCAD, which would expand the directives
+                if include::has_directives(&json::from_str(&json_)?) {
+                    println!(
+                        "Skipped {:?}, which has $include or $ref directives",
+                        file.into_os_string()
+                    );
+                    continue;
+                }
+                let (bxcad, storage) = parse_json(&json_, strict)?;
+                let Storage::Json(mut options) = storage else {
+                    unreachable!()
+                };
+                options.canonical = true;
+                let formatted = match bxcad {
+                    AnyBXCAD::BCCAD(c) => to_json(c, &options)?,
+                    AnyBXCAD::BRCAD(c) => to_json(c, &options)?,
+                } + "\n";
 
+                if formatted == json_ {
+                    continue;
+                }
+                unformatted += 1;
+                if check {
+                    println!("{:?} is not formatted", file.into_os_string());
+                } else {
+                    fs::write(&file, formatted)?;
+                    println!("Formatted {:?}", file.into_os_string());
+                }
+            }
+            if check && unformatted != 0 {
+                Err(Error::UnformattedFiles(unformatted))?
+            }
+        }
+        Command::Schema {
+            output,
+            is_bccad,
+            is_brcad,
+            indexize,
+        } => {
+            let schema_ = if is_bccad {
+                schema::for_bxcad(&BXCADType::BCCAD, indexize)?
+            } else if is_brcad {
+                schema::for_bxcad(&BXCADType::BRCAD, indexize)?
+            } else {
+                schema::for_any()?
+            };
+            let schema_ = serde_json::to_string_pretty(&schema_)?;
+            match output {
+                Some(c) => {
+                    fs::write(&c, schema_ + "\n")?;
+                    println!("Wrote JSON Schema to {:?}", c.into_os_string());
+                }
+                None => println!("{}", schema_),
+            }
+        }
+        Command::Info { bxcad, json } => {
+            let info = match read_any(&bxcad, strict)?.0 {
+                AnyBXCAD::BCCAD(c) => Info::from(&c),
+                AnyBXCAD::BRCAD(c) => Info::from(&c),
+            };
+            if json {
+                println!("{}", serde_json::to_string_pretty(&info)?);
+            } else {
+                print!("{}", info);
+            }
+        }
+        Command::Stats { paths, unknowns } => {
+            let mut corpus = Corpus::default();
+            let mut read = 0;
+            for path in files_in(&paths)? {
+                let result = (|| -> Result<bool> {
+                    let mut in_file = File::open(&path)?;
+                    // files too short to have a timestamp aren't B_CAD files either
+                    match bxcad::get_bxcad_type(&mut in_file).ok().flatten() {
+                        Some(BXCADType::BCCAD) => corpus.add(&BCCAD::from_binary(&mut in_file)?)?,
+                        Some(BXCADType::BRCAD) => corpus.add(&BRCAD::from_binary(&mut in_file)?)?,
+                        _ => return Ok(false),
+                    }
+                    Ok(true)
+                })();
+                match result {
+                    Ok(true) => read += 1,
+                    Ok(false) => {}
+                    Err(e) => eprintln!("Skipped {:?}: {}", path.as_os_str(), e),
+                }
+            }
+            println!("Read {} B_CAD files\n", read);
+            for stats in corpus.stats(unknowns) {
+                println!("{}", stats);
+            }
+        }
+        Command::Dump {
+            bxcad,
+            output,
+            is_bccad,
+            is_brcad,
+        } => {
             let mut in_file = File::open(&bxcad)?;
-
             let bxcad_type = if is_bccad {
                 BXCADType::BCCAD
             } else if is_brcad {
@@ -104,127 +1368,77 @@ fn main() -> Result<()> {
             } else {
                 bxcad::get_bxcad_type(&mut in_file)?.ok_or(Error::NotBXCAD)?
             };
-
-            if labels.is_some() && bxcad_type != BXCADType::BRCAD {
-                Err(Error::LabelsOnNotBRCAD)?
-            }
-
-            let json_ = match bxcad_type {
-                BXCADType::BCCAD => {
-                    let bccad = BCCAD::from_binary(&mut in_file)?;
-                    if indexize {
-                        let wrapper = BXCADWrapper::from_bxcad_indexize(bccad);
-                        serde_json::to_string_pretty(&wrapper)
-                    } else {
-                        let wrapper = BXCADWrapper::from_bxcad(bccad);
-                        serde_json::to_string_pretty(&wrapper)
-                    }
+            let dump = match bxcad_type {
+                BXCADType::BCCAD => BCCAD::dump(&mut in_file)?,
+                BXCADType::BRCAD => BRCAD::dump(&mut in_file)?,
+                c => Err(Error::NonImplementedFeature(format!("BXCAD type {:?}", c)))?,
+            };
+            match output {
+                Some(c) => {
+                    fs::write(&c, dump.to_string())?;
+                    println!("Wrote dump to {:?}", c.into_os_string());
                 }
-                BXCADType::BRCAD => {
-                    let mut brcad = BRCAD::from_binary(&mut in_file)?;
-                    if let Some(c) = labels {
-                        let mut labels_file = File::open(c)?;
-                        brcad.apply_labels(&mut labels_file)?;
-                    }
-                    if indexize {
-                        let wrapper = BXCADWrapper::from_bxcad_indexize(brcad);
-
-                        serde_json::to_string_pretty(&wrapper)
-                    } else {
-                        let wrapper = BXCADWrapper::from_bxcad(brcad);
-
-                        serde_json::to_string_pretty(&wrapper)
-                    }
+                None => print!("{}", dump),
+            }
+        }
+        Command::Patch {
+            command:
+                PatchCommand::Create {
+                    original,
+                    modified,
+                    patch,
+                },
+        } => {
+            let patch = match patch {
+                Some(c) => c,
+                None => {
+                    let mut p = modified.clone();
+                    p.set_extension("bps");
+                    p
                 }
-                //  BXCADType::Custom(_) => Err(Error::NonImplementedFeature(
-                //      "custom BXCAD types".to_string(),
-                //  ))?,
-                c => Err(Error::NonImplementedFeature(format!("BXCAD type {:?}", c)))?,
-            }?;
-
-            let mut out_file = File::create(&json)?;
+            };
 
-            writeln!(out_file, "{}", json_)?;
+            let source = fs::read(&original)?;
+            let patch_ = match read_any(&modified, strict)?.0 {
+                AnyBXCAD::BCCAD(c) => patch::create_from_bxcad(&source, &c)?,
+                AnyBXCAD::BRCAD(c) => patch::create_from_bxcad(&source, &c)?,
+            };
+            fs::write(&patch, patch_)?;
             println!(
-                "Serialized {:?} to {:?}",
-                bxcad.into_os_string(),
-                json.into_os_string()
+                "Created patch {:?} from {:?} to {:?}",
+                patch.into_os_string(),
+                original.into_os_string(),
+                modified.into_os_string()
             );
         }
-        Command::Deserialize { json, bxcad } => {
-            let in_file = File::open(&json)?;
-            let mut stripped = StripComments::with_settings(CommentSettings::c_style(), in_file);
-
-            let mut json_ = String::new();
-            stripped.read_to_string(&mut json_)?;
-            let value_wrapper: Value = serde_json::from_str(&json_)?;
-
-            let Some(bxcad_type) = value_wrapper.get("bxcad_type") else {
-                Err(Error::NotFlour)?
-            };
-            let Some(bxcad_type) = bxcad_type.as_str() else {
-                Err(Error::NotFlour)?
-            };
-            let bxcad_type: BXCADType = serde_json::from_str(&format!("\"{}\"", bxcad_type))?;
-
-            let bxcad = match bxcad {
+        Command::Patch {
+            command:
+                PatchCommand::Apply {
+                    original,
+                    patch,
+                    output,
+                },
+        } => {
+            let output = match output {
                 Some(c) => c,
                 None => {
-                    let mut p = json.clone();
-                    p.set_extension(match &bxcad_type {
-                        BXCADType::BCCAD => "bccad",
-                        BXCADType::BRCAD => "brcad",
-                        //  BXCADType::Custom(_) => todo!(),
-                        _ => unimplemented!(),
-                    });
-                    p
+                    // next to the original, so that it never gets overwritten
+                    let mut extension = OsString::from("patched");
+                    if let Some(c) = original.extension() {
+                        extension.push(".");
+                        extension.push(c);
+                    }
+                    original.with_extension(extension)
                 }
             };
 
-            let indexized = if let Some(bxcad_type) = value_wrapper.get("indexize") {
-                let Some(c) = bxcad_type.as_bool() else {
-                    Err(Error::NotFlour)?
-                };
-                c
-            } else {
-                false
-            };
-
-            // file is created after (de)serializing, see issue #1
-            let mut out_file;
-
-            match bxcad_type {
-                BXCADType::BCCAD => {
-                    let bccad = if indexized {
-                        let bxcad_wrapper: BXCADWrapper<IndexizedBCCAD> =
-                            serde_json::from_str(&json_)?;
-                        bxcad_wrapper.indexized_to_bxcad()?
-                    } else {
-                        let bxcad_wrapper: BXCADWrapper<BCCAD> = serde_json::from_str(&json_)?;
-                        bxcad_wrapper.to_bxcad()?
-                    };
-                    out_file = File::create(&bxcad)?;
-                    bccad.to_binary(&mut out_file)?;
-                }
-                BXCADType::BRCAD => {
-                    let brcad = if indexized {
-                        let bxcad_wrapper: BXCADWrapper<IndexizedBRCAD> =
-                            serde_json::from_str(&json_)?;
-                        bxcad_wrapper.indexized_to_bxcad()?
-                    } else {
-                        let bxcad_wrapper: BXCADWrapper<BRCAD> = serde_json::from_str(&json_)?;
-                        bxcad_wrapper.to_bxcad()?
-                    };
-                    out_file = File::create(&bxcad)?;
-                    brcad.to_binary(&mut out_file)?;
-                }
-                //  BXCADType::Custom(_) => todo!(),
-                _ => unimplemented!(),
-            }
+            let source = fs::read(&original)?;
+            let patch_ = fs::read(&patch)?;
+            fs::write(&output, patch::apply(&source, &patch_)?)?;
             println!(
-                "Deserialized {:?} to {:?}",
-                json.into_os_string(),
-                bxcad.into_os_string()
+                "Patched {:?} to {:?}",
+                original.into_os_string(),
+                output.into_os_string()
             );
         }
     }
@@ -0,0 +1,257 @@
+use crate::{
+    error::{Error, Result},
+    BXCAD,
+};
+
+const MAGIC: &[u8] = b"BPS1";
+
+const SOURCE_READ: u64 = 0;
+const TARGET_READ: u64 = 1;
+const SOURCE_COPY: u64 = 2;
+const TARGET_COPY: u64 = 3;
+
+/// Creates a BPS patch that turns `source` into `target`
+pub fn create(source: &[u8], target: &[u8]) -> Vec<u8> {
+    let mut out = MAGIC.to_vec();
+    write_number(&mut out, source.len() as u64);
+    write_number(&mut out, target.len() as u64);
+    write_number(&mut out, 0); // metadata size
+
+    let mut pos = 0;
+    while pos < target.len() {
+        // bytes that are the same as in the source, in the same place
+        let same = target[pos..]
+            .iter()
+            .zip(source.get(pos..).unwrap_or_default())
+            .take_while(|(a, b)| a == b)
+            .count();
+        if same > 0 {
+            write_number(&mut out, ((same as u64 - 1) << 2) | SOURCE_READ);
+            pos += same;
+            continue;
+        }
+
+        // bytes that are different, up until the next run of equal bytes
+        let start = pos;
+        while pos < target.len() && source.get(pos) != Some(&target[pos]) {
+            pos += 1;
+        }
+        write_number(&mut out, ((pos - start - 1) as u64) << 2 | TARGET_READ);
+        out.extend_from_slice(&target[start..pos]);
+    }
+
+    out.extend_from_slice(&crc32(source).to_le_bytes());
+    out.extend_from_slice(&crc32(target).to_le_bytes());
+    let patch_crc = crc32(&out);
+    out.extend_from_slice(&patch_crc.to_le_bytes());
+    out
+}
+
+/// Creates a BPS patch that turns `source` into the binary representation of the given BXCAD
+pub fn create_from_bxcad<X: BXCAD>(source: &[u8], target: &X) -> Result<Vec<u8>> {
+    let mut data = vec![];
+    target.to_binary(&mut data)?;
+    Ok(create(source, &data))
+}
+
+/// Applies a BPS patch to `source`, and returns the patched file
+///
+/// Patches store CRC32 checksums of the original file, the patched file and the patch itself. This
+/// fails if any of them don't match, that is, if `source` isn't the file the patch was made for or if
+/// the patch is corrupted
+pub fn apply(source: &[u8], patch: &[u8]) -> Result<Vec<u8>> {
+    if patch.len() < MAGIC.len() + 12 || !patch.starts_with(MAGIC) {
+        Err(Error::NotBPS)?
+    }
+    let footer = patch.len() - 12;
+    let checksum = |at: usize| u32::from_le_bytes(patch[at..at + 4].try_into().unwrap());
+    if crc32(&patch[..footer + 8]) != checksum(footer + 8) {
+        Err(Error::InvalidPatch)?
+    }
+    if crc32(source) != checksum(footer) {
+        Err(Error::PatchSourceMismatch)?
+    }
+
+    let mut reader = PatchReader {
+        data: &patch[..footer],
+        pos: MAGIC.len(),
+    };
+    let source_size = reader.number()? as usize;
+    let target_size = reader.number()? as usize;
+    let metadata_size = reader.number()? as usize;
+    reader.bytes(metadata_size)?;
+    if source_size != source.len() {
+        Err(Error::PatchSourceMismatch)?
+    }
+
+    // the size comes from the patch, so don't trust it to allocate more than a sane amount up front
+    let mut out: Vec<u8> = Vec::with_capacity(target_size.min(source.len() + patch.len()));
+    let mut source_pos: i64 = 0;
+    let mut target_pos: i64 = 0;
+    while reader.pos < reader.data.len() {
+        let action = reader.number()?;
+        let len = (action >> 2) as usize + 1;
+        match action & 3 {
+            SOURCE_READ => {
+                let start = out.len();
+                let Some(c) = source.get(start..start + len) else {
+                    Err(Error::InvalidPatch)?
+                };
+                out.extend_from_slice(c);
+            }
+            TARGET_READ => out.extend_from_slice(reader.bytes(len)?),
+            SOURCE_COPY => {
+                source_pos += reader.signed_number()?;
+                let Some(c) = usize::try_from(source_pos)
+                    .ok()
+                    .and_then(|c| source.get(c..c + len))
+                else {
+                    Err(Error::InvalidPatch)?
+                };
+                out.extend_from_slice(c);
+                source_pos += len as i64;
+            }
+            TARGET_COPY => {
+                target_pos += reader.signed_number()?;
+                // this can overlap with the bytes being written, so it has to go one by one
+                for _ in 0..len {
+                    let Some(c) = usize::try_from(target_pos).ok().and_then(|c| out.get(c)) else {
+                        Err(Error::InvalidPatch)?
+                    };
+                    out.push(*c);
+                    target_pos += 1;
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    if out.len() != target_size || crc32(&out) != checksum(footer + 4) {
+        Err(Error::InvalidPatch)?
+    }
+    Ok(out)
+}
+
+struct PatchReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> PatchReader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        let Some(c) = self.data.get(self.pos..self.pos + len) else {
+            Err(Error::InvalidPatch)?
+        };
+        self.pos += len;
+        Ok(c)
+    }
+
+    fn number(&mut self) -> Result<u64> {
+        let mut data = 0u64;
+        let mut shift = 1u64;
+        loop {
+            let x = self.bytes(1)?[0] as u64;
+            data = (x & 0x7f)
+                .checked_mul(shift)
+                .and_then(|c| data.checked_add(c))
+                .ok_or(Error::InvalidPatch)?;
+            if x & 0x80 != 0 {
+                return Ok(data);
+            }
+            shift = shift.checked_mul(0x80).ok_or(Error::InvalidPatch)?;
+            data = data.checked_add(shift).ok_or(Error::InvalidPatch)?;
+        }
+    }
+
+    fn signed_number(&mut self) -> Result<i64> {
+        let data = self.number()?;
+        let value = (data >> 1) as i64;
+        Ok(if data & 1 != 0 { -value } else { value })
+    }
+}
+
+fn write_number(out: &mut Vec<u8>, mut data: u64) {
+    loop {
+        let x = (data & 0x7f) as u8;
+        data >>= 7;
+        if data == 0 {
+            out.push(0x80 | x);
+            break;
+        }
+        out.push(x);
+        data -= 1;
+    }
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB88320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &[u8] = include_bytes!("../tests/fixtures/sample.bccad");
+
+    fn target() -> Vec<u8> {
+        let mut target = SOURCE.to_vec();
+        target[10] ^= 0xff;
+        target[11] ^= 0xff;
+        target.extend_from_slice(b"appended");
+        target
+    }
+
+    #[test]
+    fn round_trip() {
+        let target = target();
+        let patch = create(SOURCE, &target);
+        assert_eq!(apply(SOURCE, &patch).unwrap(), target);
+        assert_eq!(apply(SOURCE, &create(SOURCE, SOURCE)).unwrap(), SOURCE);
+    }
+
+    #[test]
+    fn bad_crc() {
+        let mut patch = create(SOURCE, &target());
+        let last = patch.len() - 13;
+        patch[last] ^= 1;
+        assert!(matches!(apply(SOURCE, &patch), Err(Error::InvalidPatch)));
+    }
+
+    #[test]
+    fn wrong_source() {
+        let patch = create(SOURCE, &target());
+        let mut source = SOURCE.to_vec();
+        source[0] ^= 1;
+        assert!(matches!(
+            apply(&source, &patch),
+            Err(Error::PatchSourceMismatch)
+        ));
+    }
+
+    #[test]
+    fn oversized_header() {
+        let target = target();
+        let mut patch = MAGIC.to_vec();
+        write_number(&mut patch, SOURCE.len() as u64);
+        write_number(&mut patch, u64::MAX >> 8);
+        write_number(&mut patch, 0);
+        write_number(&mut patch, ((target.len() as u64 - 1) << 2) | TARGET_READ);
+        patch.extend_from_slice(&target);
+        patch.extend_from_slice(&crc32(SOURCE).to_le_bytes());
+        patch.extend_from_slice(&crc32(&target).to_le_bytes());
+        let patch_crc = crc32(&patch);
+        patch.extend_from_slice(&patch_crc.to_le_bytes());
+        assert!(matches!(apply(SOURCE, &patch), Err(Error::InvalidPatch)));
+    }
+}
@@ -0,0 +1,181 @@
+use serde_json::Value;
+
+use crate::{bxcad::BXCADType, error::Result};
+
+/// JSON Schema for flour JSON files of the given BXCAD type, either indexized or not
+///
+/// Needs the `schema` feature, and indexized schemas need the `modder_qol` feature too.
+pub fn for_bxcad(bxcad_type: &BXCADType, indexize: bool) -> Result<Value> {
+    #[cfg(feature = "schema")]
+    {
+        use crate::{bxcad::BXCADWrapper, BCCAD, BRCAD};
+        use schemars::JsonSchema;
+
+        fn root<T: JsonSchema>() -> Value {
+            generator().root_schema_for::<T>().to_value()
+        }
+
+        Ok(match (bxcad_type, indexize) {
+            (BXCADType::BCCAD, false) => root::<BXCADWrapper<BCCAD>>(),
+            (BXCADType::BRCAD, false) => root::<BXCADWrapper<BRCAD>>(),
+            #[cfg(feature = "modder_qol")]
+            (BXCADType::BCCAD, true) => root::<BXCADWrapper<crate::bxcad::qol::IndexizedBCCAD>>(),
+            #[cfg(feature = "modder_qol")]
+            (BXCADType::BRCAD, true) => root::<BXCADWrapper<crate::bxcad::qol::IndexizedBRCAD>>(),
+            #[cfg(not(feature = "modder_qol"))]
+            (_, true) => Err(crate::error::Error::NonImplementedFeature(
+                "indexized schemas without the `modder_qol` feature".to_string(),
+            ))?,
+        })
+    }
+    #[cfg(not(feature = "schema"))]
+    {
+        let _ = (bxcad_type, indexize);
+        Err(unavailable())
+    }
+}
+
+/// JSON Schema for any flour JSON file, which checks each file against the schema that matches
+/// its `bxcad_type` and `indexize` fields
+///
+/// Needs the `schema` feature. Indexized files are only checked with the `modder_qol` feature.
+pub fn for_any() -> Result<Value> {
+    #[cfg(feature = "schema")]
+    {
+        use crate::{bxcad::BXCADWrapper, BCCAD, BRCAD};
+        use serde_json::json;
+
+        let mut gen = generator();
+        let bccad = gen.subschema_for::<BXCADWrapper<BCCAD>>().to_value();
+        let brcad = gen.subschema_for::<BXCADWrapper<BRCAD>>().to_value();
+        #[cfg(feature = "modder_qol")]
+        let (bccad, brcad) = {
+            use crate::bxcad::qol::{IndexizedBCCAD, IndexizedBRCAD};
+
+            let indexized = json!({
+                "properties": {"indexize": {"const": true}},
+                "required": ["indexize"],
+            });
+            let bccad_indexized = gen.subschema_for::<BXCADWrapper<IndexizedBCCAD>>();
+            let brcad_indexized = gen.subschema_for::<BXCADWrapper<IndexizedBRCAD>>();
+            (
+                json!({"if": indexized, "then": bccad_indexized, "else": bccad}),
+                json!({"if": indexized, "then": brcad_indexized, "else": brcad}),
+            )
+        };
+
+        Ok(json!({
+            "$schema": gen.settings().meta_schema,
+            "title": "flour JSON",
+            "if": {
+                "properties": {"bxcad_type": {"const": "BRCAD"}},
+                "required": ["bxcad_type"],
+            },
+            "then": brcad,
+            "else": bccad,
+            "definitions": gen.take_definitions(true),
+        }))
+    }
+    #[cfg(not(feature = "schema"))]
+    Err(unavailable())
+}
+
+/// Generator for draft 7 schemas, which is the draft that editors support best
+#[cfg(feature = "schema")]
+fn generator() -> schemars::SchemaGenerator {
+    use schemars::{generate::SchemaSettings, transform::RecursiveTransform, Schema};
+
+    // the deprecated fields are only deprecated in Rust, they've got new names in JSON
+    let not_deprecated = RecursiveTransform(|schema: &mut Schema| {
+        schema.remove("deprecated");
+    });
+    SchemaSettings::draft07()
+        .with_transform(not_deprecated)
+        .into_generator()
+}
+
+#[cfg(not(feature = "schema"))]
+fn unavailable() -> crate::error::Error {
+    crate::error::Error::NonImplementedFeature(
+        "JSON Schema generation without the `schema` feature".to_string(),
+    )
+}
+
+#[cfg(all(test, feature = "schema", feature = "modder_qol"))]
+mod tests {
+    use super::*;
+    use crate::{
+        bxcad::{minimal, qol::Indexizable, BXCADWrapper},
+        json::colors_to_hex,
+        BCCAD, BRCAD, BXCAD,
+    };
+    use jsonschema::JSONSchema;
+    use serde::Serialize;
+    use std::io::Cursor;
+
+    fn check(schema: &Value, json: &Value) {
+        let schema = JSONSchema::compile(schema).unwrap();
+        let errors: Vec<_> = match schema.validate(json) {
+            Ok(()) => return,
+            Err(errors) => errors
+                .map(|c| format!("{} at {}", c, c.instance_path))
+                .collect(),
+        };
+        panic!("fixture doesn't match the schema:\n{}", errors.join("\n"));
+    }
+
+    fn variants<X>(bxcad: X) -> Vec<(bool, Value)>
+    where
+        X: Indexizable + Serialize + Clone,
+        X::Indexized: Serialize,
+    {
+        let plain = serde_json::to_value(BXCADWrapper::from_bxcad(bxcad.clone())).unwrap();
+        let mut minimal = plain.clone();
+        minimal::omit_defaults(&mut minimal).unwrap();
+        colors_to_hex(&mut minimal["data"]);
+        minimal["omit_defaults"] = true.into();
+        minimal["hex_colors"] = true.into();
+        let indexized = BXCADWrapper::from_bxcad_indexize(bxcad.clone());
+        let named = BXCADWrapper::from_bxcad_indexize_named(bxcad);
+        vec![
+            (false, plain),
+            (false, minimal),
+            (true, serde_json::to_value(indexized).unwrap()),
+            (true, serde_json::to_value(named).unwrap()),
+        ]
+    }
+
+    #[test]
+    fn fixtures_match_the_schema() {
+        let bccad = BCCAD::from_binary(&mut Cursor::new(include_bytes!(
+            "../tests/fixtures/sample.bccad"
+        )))
+        .unwrap();
+        let brcad = BRCAD::from_binary(&mut Cursor::new(include_bytes!(
+            "../tests/fixtures/sample.brcad"
+        )))
+        .unwrap();
+        let any = for_any().unwrap();
+        for (bxcad_type, variants) in [
+            (BXCADType::BCCAD, variants(bccad)),
+            (BXCADType::BRCAD, variants(brcad)),
+        ] {
+            for (indexize, json) in variants {
+                check(&for_bxcad(&bxcad_type, indexize).unwrap(), &json);
+                check(&any, &json);
+            }
+        }
+    }
+
+    #[test]
+    fn schema_rejects_wrong_types() {
+        let bccad = BCCAD::from_binary(&mut Cursor::new(include_bytes!(
+            "../tests/fixtures/sample.bccad"
+        )))
+        .unwrap();
+        let mut json = serde_json::to_value(BXCADWrapper::from_bxcad(bccad)).unwrap();
+        json["data"]["texture_width"] = "big".into();
+        let schema = JSONSchema::compile(&for_any().unwrap()).unwrap();
+        assert!(!schema.is_valid(&json));
+    }
+}